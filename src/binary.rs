@@ -0,0 +1,54 @@
+//! Compact, versioned binary encoding of the schema, for caches and message buses.
+
+use crate::schema::RobotWorkSchema;
+
+/// Bumped whenever the binary layout of `RobotWorkSchema` changes.
+pub const VERSION: u8 = 5;
+
+impl RobotWorkSchema {
+    /// Encodes the schema as a version byte followed by its bincode encoding.
+    pub fn to_bytes(&self) -> bincode::Result<Vec<u8>> {
+        let mut bytes = vec![VERSION];
+        bincode::serialize_into(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        match bytes.split_first() {
+            Some((&VERSION, rest)) => bincode::deserialize(rest),
+            Some((version, _)) => Err(Box::new(bincode::ErrorKind::Custom(format!("unsupported schema version {}", version)))),
+            None => Err(Box::new(bincode::ErrorKind::Custom("empty input".to_string()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_test() {
+        let json_input = r#"{
+  "shift": {
+      "start": "2038-01-01T20:15:00",
+      "end": "2038-01-02T04:15:00"
+  },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25, "ramp": { "startValue": 25, "endValue": 35 } },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  }
+}"#;
+        let schema = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        let bytes = schema.to_bytes().unwrap();
+        assert_eq!(bytes[0], VERSION);
+        assert!(bytes.len() < json_input.len() / 2);
+        assert_eq!(schema.to_bytes().unwrap(), bytes);
+        assert_eq!(RobotWorkSchema::from_bytes(&bytes).unwrap(), schema);
+
+        let mut bytes = bytes;
+        bytes[0] = VERSION + 1;
+        assert!(RobotWorkSchema::from_bytes(&bytes).is_err());
+    }
+}
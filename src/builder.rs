@@ -0,0 +1,223 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::{find_range, whole_millis, RobotRateError, RobotWorkTime, TimeRange};
+
+/// How long the robot rests after each work stint.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BreakPolicy {
+    FixedRest(Duration),
+    /// Rests for a fraction of the work duration, rounded to the nearest minute.
+    RestFraction(f64),
+}
+
+impl BreakPolicy {
+    /// The rest after working for `work`, or `None` if the policy is negative or not finite.
+    pub fn rest_after(&self, work: Duration) -> Option<Duration> {
+        let rest = match *self {
+            BreakPolicy::FixedRest(rest) => rest,
+            BreakPolicy::RestFraction(fraction) if fraction.is_finite() => {
+                Duration::minutes((work.num_seconds() as f64 * fraction / 60.0).round() as i64)
+            }
+            BreakPolicy::RestFraction(_) => return None,
+        };
+        Some(rest).filter(|rest| *rest >= Duration::zero())
+    }
+}
+
+impl From<Duration> for BreakPolicy {
+    fn from(rest: Duration) -> Self {
+        BreakPolicy::FixedRest(rest)
+    }
+}
+
+/// Fluent construction of a `RobotWorkTime`, validated on `build`.
+#[derive(Debug, Clone, Default)]
+pub struct RobotWorkTimeBuilder {
+    shift: Option<(NaiveDateTime, NaiveDateTime)>,
+    time_range: Vec<TimeRange>,
+    break_policy: Option<(Duration, BreakPolicy)>,
+}
+
+impl RobotWorkTimeBuilder {
+    pub fn shift(mut self, start: NaiveDateTime, end: NaiveDateTime) -> Self {
+        self.shift = Some((start, end));
+        self
+    }
+
+    pub fn add_range(mut self, range: TimeRange) -> Self {
+        self.time_range.push(range);
+        self
+    }
+
+    /// Works for `work` before resting as given by `rest`, 8 hours and 1 hour by default.
+    pub fn break_policy(mut self, work: Duration, rest: impl Into<BreakPolicy>) -> Self {
+        self.break_policy = Some((work, rest.into()));
+        self
+    }
+
+    /// Fails if the shift is missing, the break policy is not positive, or the ranges leave any time of the week
+    /// uncovered.
+    pub fn build(self) -> Result<RobotWorkTime, RobotRateError> {
+        let (start, end) = self.shift.ok_or(RobotRateError::MissingShift)?;
+        if let Some((weekday, time)) = first_uncovered(&self.time_range) {
+            return Err(RobotRateError::UncoveredTime { weekday, time });
+        }
+        let mut robot = RobotWorkTime::new(start, end, self.time_range);
+        if let Some((work, policy)) = self.break_policy {
+            let (work_duration, rest_duration) = cadence(work, policy)?;
+            robot.work_duration = work_duration;
+            robot.rest_duration = rest_duration;
+        }
+        Ok(robot)
+    }
+}
+
+impl RobotWorkTime {
+    pub fn builder() -> RobotWorkTimeBuilder {
+        RobotWorkTimeBuilder::default()
+    }
+
+    /// The same robot taking its breaks by another policy, which is validated as by `RobotWorkTimeBuilder::build`.
+    /// Only the break layer changes: the shift, the break anchor and how the time ranges classify the shift into
+    /// tiers carry over, so a sweep over break policies costs a copy per policy.
+    pub fn rebreak(&self, work: Duration, rest: impl Into<BreakPolicy>) -> Result<RobotWorkTime, RobotRateError> {
+        let (work_duration, rest_duration) = cadence(work, rest.into())?;
+        Ok(RobotWorkTime { work_duration, rest_duration, ..self.clone() })
+    }
+}
+
+/// The work and rest durations of a break policy, failing if the work is not positive, the rest is invalid, or
+/// either is not a whole number of milliseconds, as the break cycles are counted in milliseconds.
+fn cadence(work: Duration, policy: BreakPolicy) -> Result<(Duration, Duration), RobotRateError> {
+    if work <= Duration::zero() || !whole_millis(work) {
+        return Err(RobotRateError::InvalidBreakPolicy);
+    }
+    let rest = policy.rest_after(work).ok_or(RobotRateError::InvalidBreakPolicy)?;
+    if !whole_millis(rest) {
+        return Err(RobotRateError::InvalidBreakPolicy);
+    }
+    Ok((work, rest))
+}
+
+/// The first time of the week, starting from Monday midnight, not covered by any of `time_ranges`.
+pub(crate) fn first_uncovered(time_ranges: &[TimeRange]) -> Option<(Weekday, NaiveTime)> {
+    // any week will do, this one starts on a Monday
+    let monday = NaiveDate::from_ymd(2021, 9, 6).and_hms(0, 0, 0);
+    let week_end = monday + Duration::weeks(1);
+    let mut t = monday;
+    while t < week_end {
+        if find_range(time_ranges, t).is_none() {
+            return Some((t.date().weekday(), t.time()));
+        }
+        t = time_ranges.iter()
+            .filter_map(|time_range| time_range.next_boundary_after(t))
+            .min()?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::{Segment, SegmentKind};
+    use crate::presets::{all_week, weekdays, weekend};
+
+    #[test]
+    fn build_test() {
+        let start = NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap();
+        let end = NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap();
+        let robot = RobotWorkTime::builder()
+            .shift(start, end)
+            .add_range(TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekdays()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()))
+            .break_policy(Duration::hours(4), Duration::minutes(30))
+            .build()
+            .unwrap();
+        assert_eq!(robot.work_duration, Duration::hours(4));
+        assert_eq!(robot.segments().unwrap().first().unwrap().start, start);
+        assert_eq!(robot.segments().unwrap().last().unwrap().end, end);
+    }
+
+    #[test]
+    fn build_uncovered_test() {
+        let start = NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap();
+        let end = NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap();
+        let err = RobotWorkTime::builder()
+            .shift(start, end)
+            .add_range(TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays()))
+            .build()
+            .unwrap_err();
+        assert_eq!(err, RobotRateError::UncoveredTime { weekday: Weekday::Sat, time: NaiveTime::from_hms(0, 0, 0) });
+
+        assert_eq!(RobotWorkTime::builder().build().unwrap_err(), RobotRateError::MissingShift);
+    }
+
+    #[test]
+    fn build_sub_second_cadence_test() {
+        let start = NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap();
+        let build = |work: Duration, rest: Duration| RobotWorkTime::builder()
+            .shift(start, start + Duration::hours(10))
+            .add_range(TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()))
+            .break_policy(work, rest)
+            .build();
+        assert_eq!(build(Duration::microseconds(500), Duration::zero()).unwrap_err(), RobotRateError::InvalidBreakPolicy);
+        assert_eq!(build(Duration::milliseconds(500), Duration::microseconds(1500)).unwrap_err(), RobotRateError::InvalidBreakPolicy);
+        // whole milliseconds are enough
+        assert!(build(Duration::milliseconds(500), Duration::milliseconds(600)).is_ok());
+    }
+
+    #[test]
+    fn rest_fraction_test() {
+        let robot = RobotWorkTime::builder()
+            .shift(NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap(), NaiveDateTime::from_str("2021-09-06T18:00:00").unwrap())
+            .add_range(TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()))
+            .break_policy(Duration::hours(8), BreakPolicy::RestFraction(0.1))
+            .build()
+            .unwrap();
+        assert_eq!(robot.rest_duration, Duration::minutes(48));
+        assert!(robot.segments().unwrap().contains(&Segment {
+            start: NaiveDateTime::from_str("2021-09-06T16:00:00").unwrap(),
+            end: NaiveDateTime::from_str("2021-09-06T16:48:00").unwrap(),
+            status: None,
+            kind: SegmentKind::Break,
+        }));
+
+        // 10% of 7h55m is 47.5 minutes, rounded up
+        assert_eq!(BreakPolicy::RestFraction(0.1).rest_after(Duration::minutes(475)), Some(Duration::minutes(48)));
+        assert_eq!(BreakPolicy::RestFraction(-0.1).rest_after(Duration::hours(8)), None);
+    }
+
+    #[test]
+    fn rebreak_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let robot = RobotWorkTime::new(at("2021-09-05T20:00:00"), at("2021-09-06T20:00:00"), crate::standard_ranges());
+        let short = robot.rebreak(Duration::hours(6), Duration::minutes(30)).unwrap();
+        assert_eq!(robot.rebreak(Duration::hours(8), Duration::hours(1)).unwrap().segments().unwrap(), robot.segments().unwrap());
+
+        let breaks = |robot: &RobotWorkTime| robot.segments().unwrap().into_iter()
+            .filter(|segment| segment.kind == SegmentKind::Break)
+            .map(|segment| (segment.start, segment.end))
+            .collect::<Vec<_>>();
+        assert_eq!(breaks(&robot), vec![(at("2021-09-06T04:00:00"), at("2021-09-06T05:00:00")), (at("2021-09-06T13:00:00"), at("2021-09-06T14:00:00"))]);
+        assert_eq!(breaks(&short).len(), 3);
+        assert_eq!(breaks(&short)[0], (at("2021-09-06T02:00:00"), at("2021-09-06T02:30:00")));
+
+        // wherever both work, they work in the same tier
+        for a in robot.segments().unwrap().iter().filter(|segment| segment.kind == SegmentKind::Work) {
+            for b in short.segments().unwrap().iter().filter(|segment| segment.kind == SegmentKind::Work) {
+                if a.start < b.end && b.start < a.end {
+                    assert_eq!(a.status, b.status, "{:?} and {:?}", a, b);
+                }
+            }
+        }
+
+        assert_eq!(robot.rebreak(Duration::zero(), Duration::hours(1)).unwrap_err(), RobotRateError::InvalidBreakPolicy);
+    }
+}
@@ -0,0 +1,1415 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::{find_range, BreakMode, OffsetMode, RateRamp, RobotRateError, RobotWorkTime, Segment, SegmentKind, TimeRange};
+
+/// The result of billing a shift. It is (de)serialized with the breakdown in seconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Calculation {
+    /// Total value, capped per day if the robot has a `max_daily_value`, plus the base fee.
+    pub value: u64,
+    /// The flat fee of the shift, see `RobotWorkTime::with_base_fee`.
+    #[serde(default)]
+    pub base_fee: u64,
+    /// Time worked at each time range.
+    #[serde(with = "seconds")]
+    pub breakdown: Vec<Duration>,
+    /// Value billed at each time range, including breaks billed at it by the `BreakMode`.
+    pub values: Vec<u64>,
+    pub segments: Vec<Segment>,
+    /// When the days of `daily_breakdown` start, see `RobotWorkTime::with_day_start`.
+    pub day_start: NaiveTime,
+    /// How `daily_breakdown` turns time into whole minutes, see `RobotWorkTime::with_minute_rounding`.
+    #[serde(default = "MinuteRounding::floor")]
+    pub minute_rounding: MinuteRounding,
+}
+
+/// What the rates are quoted per. Time is billed by the minute either way.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RateUnit {
+    PerMinute,
+    PerHour,
+}
+
+impl RateUnit {
+    /// The default, for schemas without a rate unit.
+    pub(crate) fn per_minute() -> Self {
+        RateUnit::PerMinute
+    }
+
+    /// Minutes the rates are quoted per.
+    pub fn minutes(self) -> u64 {
+        match self {
+            RateUnit::PerMinute => 1,
+            RateUnit::PerHour => 60,
+        }
+    }
+
+    /// `value` billed at rates quoted per this unit as if they were per minute, brought back to the value billed,
+    /// rounded half up.
+    fn scale(self, value: u64) -> u64 {
+        (value + self.minutes() / 2) / self.minutes()
+    }
+}
+
+/// How the time accumulated at a rate is turned into the whole minutes it is billed for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MinuteRounding {
+    /// Leftover seconds are not billed, the default.
+    Floor,
+    /// Leftover seconds bill a whole minute from 30 seconds on.
+    Round,
+    /// Leftover seconds bill a whole minute.
+    Ceil,
+}
+
+impl MinuteRounding {
+    /// The default, for calculations serialized without a rounding.
+    fn floor() -> Self {
+        MinuteRounding::Floor
+    }
+
+    /// Whole minutes billed for `seconds`.
+    pub fn minutes(self, seconds: i64) -> i64 {
+        match self {
+            MinuteRounding::Floor => seconds / 60,
+            MinuteRounding::Round => (seconds + 30) / 60,
+            MinuteRounding::Ceil => (seconds + 59) / 60,
+        }
+    }
+}
+
+/// `Duration`s as whole seconds.
+mod seconds {
+    use chrono::Duration;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(durations: &[Duration], serializer: S) -> Result<S::Ok, S::Error> {
+        durations.iter().map(|duration| duration.num_seconds()).collect::<Vec<_>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Duration>, D::Error> {
+        Ok(Vec::<i64>::deserialize(deserializer)?.into_iter().map(Duration::seconds).collect())
+    }
+}
+
+/// Time and value billed at the time ranges of a report group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportGroup {
+    pub name: String,
+    pub duration: Duration,
+    pub value: u64,
+}
+
+/// A stint in one time range, reported as a single item even when breaks split it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineItem {
+    pub status: usize,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    /// Time billed, i.e. from `start` to `end` without the breaks.
+    pub duration: Duration,
+    pub break_minutes_excluded: i64,
+}
+
+/// Difference in billing of another configuration over the same shift, i.e. the other minus this one.
+/// Time ranges are compared by index, a missing one counting as unbilled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BillingDiff {
+    pub breakdown: Vec<Duration>,
+    pub values: Vec<i64>,
+    pub value: i64,
+}
+
+/// A billed part of a segment, at a single rate.
+struct Piece {
+    idx: usize,
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    /// Index of the rates in effect, see `RobotWorkTime::rate_in`.
+    period: usize,
+    /// Index of the streak multiplier, see `RobotWorkTime::streak_multiplier`.
+    step: usize,
+}
+
+/// Which day a minute of billed time straddling the start of a day is reported on by
+/// `Calculation::daily_breakdown_with`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Midnight {
+    /// The instant the day starts belongs to the new day, as at the `00:00` transitions of the iterator.
+    NewDay,
+    /// The instant the day starts belongs to the day it ends.
+    EndingDay,
+}
+
+impl Calculation {
+    /// Sums the breakdown by report group, `groups[idx]` naming the group of the time range at `idx`.
+    /// Groups are listed by their first appearance in `groups`. Fails unless there is a group for each time range.
+    pub fn grouped_breakdown(&self, groups: &[&str]) -> Result<Vec<ReportGroup>, RobotRateError> {
+        if groups.len() != self.breakdown.len() {
+            return Err(RobotRateError::GroupCountMismatch { ranges: self.breakdown.len(), groups: groups.len() });
+        }
+        let mut ret: Vec<ReportGroup> = vec![];
+        for ((name, duration), value) in groups.iter().zip(self.breakdown.iter()).zip(self.values.iter()) {
+            match ret.iter_mut().find(|group| group.name == *name) {
+                Some(group) => {
+                    group.duration = group.duration + *duration;
+                    group.value += value;
+                }
+                None => ret.push(ReportGroup { name: name.to_string(), duration: *duration, value: *value }),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The breakdown in decimal hours, keeping partial minutes.
+    pub fn breakdown_hours(&self) -> Vec<f64> {
+        self.breakdown.iter().map(|duration| duration.num_seconds() as f64 / 3600.0).collect()
+    }
+
+    /// Coalesces the work segments of a time range which are split only by breaks (or by midnight) into one line
+    /// item each. Maintenance and off-duty time always end an item.
+    pub fn line_items(&self) -> Vec<LineItem> {
+        let mut ret: Vec<LineItem> = vec![];
+        let mut breaks = Duration::zero();
+        let mut open = false;
+        for segment in self.segments.iter() {
+            match (segment.kind, segment.status) {
+                (SegmentKind::Work, Some(status)) => {
+                    match ret.last_mut() {
+                        Some(item) if open && item.status == status => {
+                            item.end = segment.end;
+                            item.duration = item.duration + segment.duration();
+                            item.break_minutes_excluded += breaks.num_minutes();
+                        }
+                        _ => ret.push(LineItem {
+                            status,
+                            start: segment.start,
+                            end: segment.end,
+                            duration: segment.duration(),
+                            break_minutes_excluded: 0,
+                        }),
+                    }
+                    breaks = Duration::zero();
+                    open = true;
+                }
+                (SegmentKind::Break, _) => breaks = breaks + segment.duration(),
+                _ => {
+                    breaks = Duration::zero();
+                    open = false;
+                }
+            }
+        }
+        ret
+    }
+
+    /// Splits the breakdown by day, in whole minutes. Days start at `day_start`, midnight by default, and are
+    /// dated by the calendar day they start on. A minute straddling the start of a day goes to the new day.
+    pub fn daily_breakdown(&self) -> Vec<(NaiveDate, Vec<Duration>)> {
+        self.daily_breakdown_with(Midnight::NewDay)
+    }
+
+    /// Like `daily_breakdown`, but a minute straddling the start of a day goes to the day chosen by `midnight`.
+    /// Either way the days sum up to the minutes of the breakdown rounded by `minute_rounding`, as billed by
+    /// `calculate`, the last day taking any minute rounded up.
+    pub fn daily_breakdown_with(&self, midnight: Midnight) -> Vec<(NaiveDate, Vec<Duration>)> {
+        let pieces = self.segments.iter()
+            .filter_map(|segment| Some((segment.status?, segment.start, segment.end)));
+        minutes_by_day(pieces, self.breakdown.len(), self.day_start, midnight, self.minute_rounding)
+    }
+}
+
+/// Splits `[s, end)` into the days starting at `day_start`, each dated by the calendar day it starts on.
+fn split_days(mut s: NaiveDateTime, end: NaiveDateTime, day_start: NaiveTime) -> Vec<(NaiveDate, NaiveDateTime, NaiveDateTime)> {
+    let mut ret = vec![];
+    while s < end {
+        let (date, next_day) = if s.time() >= day_start {
+            (s.date(), s.date().succ_opt().map(|d| d.and_time(day_start)))
+        } else {
+            (s.date().pred_opt().unwrap_or_else(|| s.date()), Some(s.date().and_time(day_start)))
+        };
+        let e = next_day.map_or(end, |next_day| next_day.min(end));
+        ret.push((date, s, e));
+        s = e;
+    }
+    ret
+}
+
+/// Whole minutes per key and day of the `(key, start, end)` pieces, see `Calculation::daily_breakdown_with`.
+fn minutes_by_day(
+    pieces: impl Iterator<Item=(usize, NaiveDateTime, NaiveDateTime)>,
+    keys: usize,
+    day_start: NaiveTime,
+    midnight: Midnight,
+    rounding: MinuteRounding,
+) -> Vec<(NaiveDate, Vec<Duration>)> {
+    let mut days: Vec<(NaiveDate, Vec<i64>)> = vec![];
+    for (key, s, end) in pieces {
+        for (date, s, e) in split_days(s, end, day_start) {
+            if days.last().map(|(date, _)| *date) != Some(date) {
+                days.push((date, vec![0; keys]));
+            }
+            days.last_mut().unwrap().1[key] += (e - s).num_seconds();
+        }
+    }
+
+    // minutes up to each start of a day, rounded so that the last day ends at the billed total
+    let totals = (0..keys)
+        .map(|key| rounding.minutes(days.iter().map(|(_, seconds)| seconds[key]).sum::<i64>()))
+        .collect::<Vec<_>>();
+    let last = days.len().saturating_sub(1);
+    let mut seconds = vec![0; keys];
+    let mut minutes = vec![0; keys];
+    days.into_iter()
+        .enumerate()
+        .map(|(day, (date, day_seconds))| {
+            let durations = (0..keys).map(|key| {
+                seconds[key] += day_seconds[key];
+                let until = match midnight {
+                    _ if day == last => totals[key],
+                    Midnight::NewDay => (seconds[key] / 60).min(totals[key]),
+                    Midnight::EndingDay => ((seconds[key] + 59) / 60).min(totals[key]),
+                };
+                let ret = Duration::minutes(until - minutes[key]);
+                minutes[key] = until;
+                ret
+            }).collect();
+            (date, durations)
+        })
+        .collect()
+}
+
+impl RobotWorkTime {
+    /// Bills the shift, where `rates[idx]` is the per-minute rate of the time range at `idx`, until the first rate
+    /// change (see `with_rate_change`).
+    ///
+    /// Flat rates are charged per whole minute accumulated in each time range between rate changes, rounded as by
+    /// `with_minute_rounding`, and brought to the minute from the unit of `with_rate_unit` per time range, while
+    /// ramped ranges are integrated over each covered segment (see `RateRamp::integrate`).
+    pub fn calculate(&self, rates: &[u64]) -> Result<Calculation, RobotRateError> {
+        self.check_representable()?;
+        self.bill(rates, self.segments()?)
+    }
+
+    /// Change in value if the robot took an extra break of `duration` from `at`, which is negative or zero.
+    /// The extra break does not move the regular breaks.
+    pub fn cost_of_extra_break(&self, at: NaiveDateTime, duration: Duration, rates: &[u64]) -> Result<i64, RobotRateError> {
+        let segments = self.segments()?;
+        let break_end = at.checked_add_signed(duration).ok_or(RobotRateError::DateOutOfRange)?;
+        let with_break = insert_break(&segments, at, break_end);
+        Ok(self.bill(rates, with_break)?.value as i64 - self.bill(rates, segments)?.value as i64)
+    }
+
+    /// Value saved by moving the break starting at `at` by `by`, which is negative if the move costs more, or zero
+    /// if no break starts at `at`. The robot works through the old break and rests during the moved one, while
+    /// the other breaks stay put.
+    pub fn saving_of_moving_break(&self, at: NaiveDateTime, by: Duration, rates: &[u64]) -> Result<i64, RobotRateError> {
+        let segments = self.segments()?;
+        // a split break spans consecutive break segments
+        let mut old_end = at;
+        for segment in segments.iter().skip_while(|segment| segment.start != at) {
+            if segment.kind != SegmentKind::Break || segment.start != old_end {
+                break;
+            }
+            old_end = segment.end;
+        }
+        if old_end == at {
+            return Ok(0);
+        }
+        let moved_start = at.checked_add_signed(by).ok_or(RobotRateError::DateOutOfRange)?;
+        let moved_end = old_end.checked_add_signed(by).ok_or(RobotRateError::DateOutOfRange)?;
+
+        let worked_through = segments.iter()
+            .flat_map(|segment| match segment.kind {
+                SegmentKind::Break if at <= segment.start && segment.end <= old_end => self.work_segments(segment.start, segment.end),
+                _ => vec![*segment],
+            })
+            .collect::<Vec<_>>();
+        let moved = insert_break(&worked_through, moved_start, moved_end);
+        Ok(self.bill(rates, segments)?.value as i64 - self.bill(rates, moved)?.value as i64)
+    }
+
+    /// Work segments from `from` to `to`, split at the transitions of the time ranges.
+    fn work_segments(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<Segment> {
+        let mut cuts = self.time_range.iter()
+            .flat_map(|range| range.pieces_between(from, to))
+            .flat_map(|(s, e)| vec![s, e])
+            .chain(vec![from, to])
+            .collect::<Vec<_>>();
+        cuts.sort();
+        cuts.dedup();
+        cuts.windows(2)
+            .map(|w| Segment { start: w[0], end: w[1], status: find_range(&self.time_range, w[0]), kind: SegmentKind::Work })
+            .collect()
+    }
+
+    /// Fails early if walking the shift may run past the representable dates: the day before the start, and a
+    /// break cycle and a day after the end must be representable.
+    pub fn check_representable(&self) -> Result<(), RobotRateError> {
+        let margin = self.work_duration + self.rest_duration + Duration::days(1);
+        self.start.checked_sub_signed(Duration::days(1))
+            .and(self.end.checked_add_signed(margin))
+            .map(|_| ())
+            .ok_or(RobotRateError::DateOutOfRange)
+    }
+
+    /// `segments` of another robot on the same shift, with the work split and classified by the time ranges of
+    /// this one, so that the same break placement can be billed by other ranges without walking the shift again.
+    pub(crate) fn reclassify(&self, segments: &[Segment]) -> Vec<Segment> {
+        segments.iter()
+            .flat_map(|segment| match segment.kind {
+                SegmentKind::Work => self.work_segments(segment.start, segment.end),
+                _ => vec![*segment],
+            })
+            .collect()
+    }
+
+    /// Bills `segments` of the shift, see `calculate`.
+    pub(crate) fn bill(&self, rates: &[u64], segments: Vec<Segment>) -> Result<Calculation, RobotRateError> {
+        if rates.len() != self.time_range.len() {
+            return Err(RobotRateError::RateCountMismatch { ranges: self.time_range.len(), rates: rates.len() });
+        }
+        if let Some((_, changed)) = self.rate_changes.iter().find(|(_, changed)| changed.len() != rates.len()) {
+            return Err(RobotRateError::RateCountMismatch { ranges: self.time_range.len(), rates: changed.len() });
+        }
+
+        if self.start < self.end && segments.last().map(|segment| segment.end) != Some(self.end) {
+            return Err(RobotRateError::DateOutOfRange);
+        }
+        let mut breakdown = vec![Duration::zero(); rates.len()];
+        let mut ramped = vec![0.0; rates.len()];
+        let mut flat: BTreeMap<(usize, usize), Vec<Duration>> = BTreeMap::new();
+        for Piece { idx, start: s, end: e, period, step } in self.pieces(&segments) {
+            breakdown[idx] = breakdown[idx] + (e - s);
+            if let Some(ramp) = self.ramp_in(period, idx) {
+                let window = self.time_range[idx].occurrence_at(s).unwrap();
+                ramped[idx] += ramp.integrate(window, s, e) * self.streak_multiplier(step);
+            }
+            let durations = flat.entry((period, step)).or_insert_with(|| vec![Duration::zero(); rates.len()]);
+            durations[idx] = durations[idx] + (e - s);
+        }
+
+        let values = (0..rates.len())
+            .map(|idx| {
+                // ramped ranges are billed flat during overrides only
+                let ramp = self.ramps.contains_key(&idx);
+                let buckets = flat.iter().filter(|((period, _), _)| !ramp || self.is_override(*period));
+                let flat_value = if self.streak_multipliers.is_empty() {
+                    buckets
+                        .map(|((period, _), durations)| self.billable_minutes(durations[idx]) * self.rate_in(*period, rates, idx))
+                        .sum()
+                } else {
+                    buckets
+                        .map(|((period, step), durations)| {
+                            (self.billable_minutes(durations[idx]) * self.rate_in(*period, rates, idx)) as f64 * self.streak_multiplier(*step)
+                        })
+                        .sum::<f64>()
+                        .round() as u64
+                };
+                if ramp { flat_value + ramped[idx].round() as u64 } else { flat_value }
+            })
+            .collect::<Vec<_>>();
+        let mut break_values = vec![0.0; rates.len()];
+        for (_, idx, value) in self.break_charges(&segments, rates) {
+            break_values[idx] += value;
+        }
+        let values = values.iter()
+            .zip(break_values)
+            .map(|(value, charge)| self.rate_unit.scale(value + charge.round() as u64))
+            .collect::<Vec<_>>();
+
+        let base_fee = if self.start < self.end { self.base_fee } else { 0 };
+        let mut c = Calculation {
+            value: values.iter().sum::<u64>() + base_fee,
+            base_fee,
+            breakdown,
+            values,
+            segments,
+            day_start: self.day_start,
+            minute_rounding: self.minute_rounding,
+        };
+        if let Some(cap) = self.max_daily_value {
+            c.value = self.daily_values(&c, rates).iter().map(|(_, value)| (*value).min(cap)).sum::<u64>() + base_fee;
+        }
+        Ok(c)
+    }
+
+    /// Whole minutes billed for `duration`, see `with_minute_rounding`.
+    fn billable_minutes(&self, duration: Duration) -> u64 {
+        self.minute_rounding.minutes(duration.num_seconds()) as u64
+    }
+
+    /// The rate of the time range at `idx` in `period`: the base `rates` before the first rate change, the rates
+    /// of each rate change after it, and past those the rate of each override, whatever the range.
+    fn rate_in(&self, period: usize, rates: &[u64], idx: usize) -> u64 {
+        match period {
+            _ if self.is_override(period) => self.overrides()[period - self.rate_changes.len() - 1].1,
+            _ if self.offset_mode == OffsetMode::Replace => 0,
+            0 => rates[idx],
+            _ => self.rate_changes[period - 1].1[idx],
+        }
+    }
+
+    /// The ramp billing the time range at `idx` in `period`, none within overrides or under `OffsetMode::Replace`.
+    fn ramp_in(&self, period: usize, idx: usize) -> Option<&RateRamp> {
+        match self.offset_mode {
+            OffsetMode::Overlay if !self.is_override(period) => self.ramps.get(&idx),
+            _ => None,
+        }
+    }
+
+    /// Whether `period` is that of an override, see `rate_in`.
+    fn is_override(&self, period: usize) -> bool {
+        period > self.rate_changes.len()
+    }
+
+    /// The windows billed at a flat rate whatever the time range, by increasing precedence: the offset ranges, the
+    /// task overrides, then the overrun of `with_soft_end`.
+    fn overrides(&self) -> Vec<((NaiveDateTime, NaiveDateTime), u64)> {
+        let start = self.start;
+        let mut ret = self.offset_ranges.iter()
+            .filter_map(|range| Some(((start.checked_add_signed(range.from_offset)?, start.checked_add_signed(range.to_offset)?), range.value)))
+            .collect::<Vec<_>>();
+        ret.extend(self.task_overrides.iter().copied());
+        ret.extend(self.soft_end.map(|(scheduled, rate)| ((scheduled, self.end), rate)));
+        ret
+    }
+
+    /// Splits the part `[from, to)` of rate `period` at the overrides, the parts within an override taking its
+    /// period instead.
+    fn override_periods(&self, period: usize, from: NaiveDateTime, to: NaiveDateTime) -> Vec<(usize, NaiveDateTime, NaiveDateTime)> {
+        let overrides = self.overrides();
+        if overrides.is_empty() {
+            return vec![(period, from, to)];
+        }
+        let mut cuts = overrides.iter()
+            .flat_map(|((s, e), _)| vec![*s, *e])
+            .filter(|t| from < *t && *t < to)
+            .chain(vec![from, to])
+            .collect::<Vec<_>>();
+        cuts.sort();
+        cuts.dedup();
+        cuts.windows(2)
+            .map(|w| {
+                let window = overrides.iter().rposition(|((s, e), _)| *s <= w[0] && w[0] < *e);
+                (window.map_or(period, |window| self.rate_changes.len() + 1 + window), w[0], w[1])
+            })
+            .collect()
+    }
+
+    /// Splits `[from, to)` at the rate changes, with the period of each part.
+    fn rate_periods(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<(usize, NaiveDateTime, NaiveDateTime)> {
+        let mut ret = vec![];
+        let mut s = from;
+        let mut period = self.rate_changes.iter().take_while(|(effective, _)| *effective <= from).count();
+        while s < to {
+            let e = self.rate_changes.get(period).map_or(to, |(effective, _)| (*effective).min(to));
+            ret.push((period, s, e));
+            s = e;
+            period += 1;
+        }
+        ret
+    }
+
+    /// The multiplier of streak `step`, i.e. after the first `step` thresholds of `with_streak_multipliers`.
+    fn streak_multiplier(&self, step: usize) -> f64 {
+        match step {
+            0 => 1.0,
+            _ => self.streak_multipliers[step - 1].1,
+        }
+    }
+
+    /// The billed parts of `segments`, split at rate changes and streak thresholds. A streak of work starts at the
+    /// shift start and after each break.
+    fn pieces(&self, segments: &[Segment]) -> Vec<Piece> {
+        let mut ret = vec![];
+        let mut streak = Duration::zero();
+        for segment in segments.iter() {
+            // a segment ending before it starts would reduce the bill
+            debug_assert!(segment.end >= segment.start, "segment ends before it starts: {:?}", segment);
+            if segment.end < segment.start {
+                tracing::warn!("not billing segment {:?}, which ends before it starts", segment);
+                continue;
+            }
+            let idx = match (segment.kind, segment.status) {
+                (SegmentKind::Work, Some(idx)) => idx,
+                (SegmentKind::Break, _) => {
+                    streak = Duration::zero();
+                    continue;
+                }
+                _ => continue,
+            };
+            let periods = self.rate_periods(segment.start, segment.end).into_iter()
+                .flat_map(|(period, s, e)| self.override_periods(period, s, e));
+            for (period, mut s, end) in periods {
+                while s < end {
+                    let step = self.streak_multipliers.iter().take_while(|(after, _)| *after <= streak).count();
+                    let e = self.streak_multipliers.get(step).map_or(end, |(after, _)| (s + (*after - streak)).min(end));
+                    ret.push(Piece { idx, start: s, end: e, period, step });
+                    streak = streak + (e - s);
+                    s = e;
+                }
+            }
+        }
+        ret
+    }
+
+    /// What each break of `segments` is billed by `BreakMode`, with the day it starts on and the time range it is
+    /// billed at. Maintenance during a break does not change the range it interrupted. Split breaks (see
+    /// `with_split_breaks`) are billed at the range current at the start of each part.
+    fn break_charges(&self, segments: &[Segment], rates: &[u64]) -> Vec<(NaiveDate, usize, f64)> {
+        let fraction = match self.break_mode {
+            BreakMode::Unbilled => return vec![],
+            BreakMode::FractionOfInterruptedTier(fraction) => fraction,
+        };
+        let mut ret = vec![];
+        let mut interrupted = None;
+        for segment in segments.iter() {
+            match segment.kind {
+                SegmentKind::Work => interrupted = None,
+                SegmentKind::Maintenance | SegmentKind::OffDuty => {}
+                SegmentKind::Break => {
+                    if interrupted.is_none() || self.split_breaks {
+                        interrupted = find_range(&self.time_range, segment.start);
+                    }
+                    let idx = match interrupted {
+                        Some(idx) => idx,
+                        None => continue,
+                    };
+                    for (period, s, e) in self.rate_periods(segment.start, segment.end) {
+                        let value = (self.billable_minutes(e - s) * self.rate_in(period, rates, idx)) as f64 * fraction;
+                        let date = split_days(s, e, self.day_start)[0].0;
+                        ret.push((date, idx, value));
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// Value of each day of `c`, flat rates billed on whole minutes per day as by `Calculation::daily_breakdown`.
+    /// Billed breaks go to the day they start on.
+    fn daily_values(&self, c: &Calculation, rates: &[u64]) -> Vec<(NaiveDate, u64)> {
+        let pieces = self.pieces(&c.segments);
+        let mut ramped: Vec<(NaiveDate, f64)> = vec![];
+        for &Piece { idx, start, end, period, step } in pieces.iter() {
+            let ramp = match self.ramp_in(period, idx) {
+                Some(ramp) => ramp,
+                None => continue,
+            };
+            // billed segments never cross midnight, but may cross the start of a day
+            let window = self.time_range[idx].occurrence_at(start).unwrap();
+            for (day, s, e) in split_days(start, end, self.day_start) {
+                let value = ramp.integrate(window, s, e) * self.streak_multiplier(step);
+                match ramped.last_mut() {
+                    Some((date, total)) if *date == day => *total += value,
+                    _ => ramped.push((day, value)),
+                }
+            }
+        }
+
+        // flat minutes are kept apart per rate period and streak step, keyed by `(period * steps + step) * tiers + idx`
+        let breaks = self.break_charges(&c.segments, rates);
+        let tiers = rates.len();
+        let steps = self.streak_multipliers.len() + 1;
+        let periods = self.rate_changes.len() + 1 + self.overrides().len();
+        let keys = pieces.iter()
+            .filter(|piece| !self.ramps.contains_key(&piece.idx) || self.is_override(piece.period))
+            .map(|piece| ((piece.period * steps + piece.step) * tiers + piece.idx, piece.start, piece.end));
+        minutes_by_day(keys, periods * steps * tiers, self.day_start, Midnight::NewDay, self.minute_rounding).into_iter()
+            .map(|(date, durations)| {
+                let flat = durations.iter().enumerate()
+                    .map(|(key, duration)| {
+                        let (period, step, idx) = (key / tiers / steps, key / tiers % steps, key % tiers);
+                        (duration.num_minutes() as u64 * self.rate_in(period, rates, idx)) as f64 * self.streak_multiplier(step)
+                    })
+                    .sum::<f64>();
+                let ramped = ramped.iter()
+                    .find(|(d, _)| *d == date)
+                    .map_or(0.0, |(_, value)| *value);
+                let breaks = breaks.iter()
+                    .filter(|(d, _, _)| *d == date)
+                    .map(|(_, _, value)| value)
+                    .sum::<f64>();
+                (date, self.rate_unit.scale((flat + ramped + breaks).round() as u64))
+            })
+            .collect()
+    }
+
+    /// Total value of the shift, see `calculate`.
+    pub fn value(&self, rates: &[u64]) -> Result<u64, RobotRateError> {
+        Ok(self.calculate(rates)?.value)
+    }
+
+    /// Bills the shift at the rates of `with_rate_table`, see `calculate`.
+    pub fn total_calculation(&self) -> Result<Calculation, RobotRateError> {
+        self.calculate(self.rate_table.as_ref().ok_or(RobotRateError::MissingRateTable)?)
+    }
+
+    /// Total value of the shift at the rates of `with_rate_table`.
+    pub fn total_value(&self) -> Result<u64, RobotRateError> {
+        Ok(self.total_calculation()?.value)
+    }
+
+    /// The start of a shift lasting `duration` within `window`, tried every `step` from its start, that is billed
+    /// the least, and its value. The earliest start wins a tie. Only the window start is tried if `step` is not
+    /// positive.
+    pub fn cheapest_start(
+        duration: Duration,
+        window: (NaiveDateTime, NaiveDateTime),
+        step: Duration,
+        time_range: impl Into<Arc<Vec<TimeRange>>>,
+        rates: &[u64],
+    ) -> Result<(NaiveDateTime, u64), RobotRateError> {
+        // shared by every candidate rather than copied for each
+        let time_range: Arc<Vec<TimeRange>> = time_range.into();
+        let mut start = window.0;
+        let mut cheapest: Option<(NaiveDateTime, u64)> = None;
+        loop {
+            let end = start.checked_add_signed(duration).ok_or(RobotRateError::DateOutOfRange)?;
+            let value = RobotWorkTime::new(start, end, Arc::clone(&time_range)).value(rates)?;
+            if cheapest.iter().all(|(_, min)| value < *min) {
+                cheapest = Some((start, value));
+            }
+            match start.checked_add_signed(step) {
+                Some(next) if step > Duration::zero() && next <= window.1 => start = next,
+                _ => break,
+            }
+        }
+        Ok(cheapest.unwrap())
+    }
+
+    /// Value of the shift worked up to `now`, billed as if the shift ended then, e.g. for a shift in progress.
+    /// Billing stops at the shift end. Fails as `calculate` does.
+    pub fn accrued_value(&self, now: NaiveDateTime, rates: &[u64]) -> Result<u64, RobotRateError> {
+        let mut t = self.clone();
+        t.end = now.max(self.start).min(self.end);
+        t.value(rates)
+    }
+
+    /// Bills the shift of this robot under `other` as well, and returns how that changes billing.
+    /// `rates` and `other_rates` price the time ranges of this robot and `other` respectively. The shift is taken as
+    /// this robot bills it, with its inclusive or soft end, and `other` keeps only its overtime rate, billed over
+    /// the overrun of this robot if both have a soft end.
+    pub fn validate_against(&self, other: &RobotWorkTime, rates: &[u64], other_rates: &[u64]) -> Result<BillingDiff, RobotRateError> {
+        let mut other = other.clone();
+        other.start = self.start;
+        other.end = self.end;
+        other.inclusive_end = self.inclusive_end;
+        other.soft_end = match (self.soft_end, other.soft_end) {
+            (Some((scheduled, _)), Some((_, rate))) => Some((scheduled, rate)),
+            _ => None,
+        };
+        other.break_anchor = other.break_anchor.min(self.start);
+
+        let (before, after) = (self.calculate(rates)?, other.calculate(other_rates)?);
+        let tiers = before.breakdown.len().max(after.breakdown.len());
+        let duration = |c: &Calculation, idx: usize| c.breakdown.get(idx).copied().unwrap_or_else(Duration::zero);
+        let value = |c: &Calculation, idx: usize| c.values.get(idx).copied().unwrap_or(0) as i64;
+        let breakdown = (0..tiers).map(|idx| duration(&after, idx) - duration(&before, idx)).collect();
+        let values = (0..tiers).map(|idx| value(&after, idx) - value(&before, idx)).collect();
+        Ok(BillingDiff { breakdown, values, value: after.value as i64 - before.value as i64 })
+    }
+
+    /// Blended per-minute rate of the shift, i.e. its value over the minutes worked in time ranges, or 0 if none,
+    /// e.g. for an empty shift.
+    pub fn equivalent_flat_rate(&self, rates: &[u64]) -> Result<f64, RobotRateError> {
+        let c = self.calculate(rates)?;
+        let minutes = c.breakdown.iter().fold(Duration::zero(), |acc, duration| acc + *duration).num_seconds() as f64 / 60.0;
+        Ok(if minutes > 0.0 { c.value as f64 / minutes } else { 0.0 })
+    }
+
+    /// Bills the shift and reports it by group, see `Calculation::grouped_breakdown`.
+    pub fn merge_rates(&self, rates: &[u64], groups: &[&str]) -> Result<Vec<ReportGroup>, RobotRateError> {
+        if groups.len() != self.time_range.len() {
+            return Err(RobotRateError::GroupCountMismatch { ranges: self.time_range.len(), groups: groups.len() });
+        }
+        self.calculate(rates)?.grouped_breakdown(groups)
+    }
+
+    /// Length of the shift, breaks included.
+    pub fn shift_duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// Time spent working in time ranges, i.e. the shift without its breaks and maintenance.
+    pub fn working_duration(&self) -> Result<Duration, RobotRateError> {
+        Ok(self.segments()?.iter()
+            .filter(|segment| segment.status.is_some())
+            .fold(Duration::zero(), |acc, segment| acc + segment.duration()))
+    }
+
+    /// Whole minutes of `working_duration`, rounded by `with_minute_rounding`. 0 for an empty shift.
+    pub fn billed_minutes(&self) -> Result<u64, RobotRateError> {
+        Ok(self.billable_minutes(self.working_duration()?))
+    }
+
+    /// Minutes worked in each hour of the day, by weekday from Monday, e.g. for capacity planning. The time is summed
+    /// per hour before being cut to whole minutes.
+    pub fn hourly_heatmap(&self) -> Result<[[u32; 24]; 7], RobotRateError> {
+        let mut seconds = [[0; 24]; 7];
+        for segment in self.segments()?.iter().filter(|segment| segment.status.is_some()) {
+            let mut s = segment.start;
+            while s < segment.end {
+                let next_hour = s.date().and_hms(s.hour(), 0, 0).checked_add_signed(Duration::hours(1));
+                let e = next_hour.map_or(segment.end, |next_hour| next_hour.min(segment.end));
+                seconds[s.weekday().num_days_from_monday() as usize][s.hour() as usize] += (e - s).num_seconds();
+                s = e;
+            }
+        }
+        let mut ret = [[0; 24]; 7];
+        for (day, hours) in seconds.iter().enumerate() {
+            for (hour, seconds) in hours.iter().enumerate() {
+                ret[day][hour] = (seconds / 60) as u32;
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Indices of the time ranges worked in for a nonzero duration.
+    pub fn tiers_touched(&self) -> Result<BTreeSet<usize>, RobotRateError> {
+        Ok(self.segments()?.iter()
+            .filter(|segment| segment.duration() > Duration::zero())
+            .filter_map(|segment| segment.status)
+            .collect())
+    }
+
+    /// Fraction of the shift spent working in time ranges with a non-zero rate, or 0 for an empty shift rather
+    /// than `NaN`. Unlike `billed_minutes`, which counts the work in every time range, work at a zero rate is left
+    /// out, and partial minutes are kept.
+    pub fn paid_ratio(&self, rates: &[u64]) -> Result<f64, RobotRateError> {
+        let shift = self.shift_duration().num_seconds();
+        if shift <= 0 {
+            return Ok(0.0);
+        }
+        let billed = self.segments()?.iter()
+            .filter(|segment| matches!(segment.status, Some(idx) if rates.get(idx).copied().unwrap_or(0) > 0))
+            .fold(Duration::zero(), |acc, segment| acc + segment.duration());
+        Ok(billed.num_seconds() as f64 / shift as f64)
+    }
+}
+
+/// `segments` with a break from `at` to `end` in place of the work in between.
+fn insert_break(segments: &[Segment], at: NaiveDateTime, end: NaiveDateTime) -> Vec<Segment> {
+    let mut ret = vec![];
+    for segment in segments.iter() {
+        if segment.kind != SegmentKind::Work || segment.end <= at || segment.start >= end {
+            ret.push(*segment);
+            continue;
+        }
+        if segment.start < at {
+            ret.push(Segment { end: at, ..*segment });
+        }
+        ret.push(Segment { start: segment.start.max(at), end: segment.end.min(end), status: None, kind: SegmentKind::Break });
+        if segment.end > end {
+            ret.push(Segment { start: end, ..*segment });
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use chrono::{naive::MAX_DATE, NaiveDateTime, NaiveTime};
+
+    use crate::presets::all_week;
+    use crate::{standard_ranges, OffsetRange, TimeRange};
+
+    use super::*;
+
+    #[test]
+    fn calculate_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap(),
+            standard_ranges(),
+        );
+        let c = t.calculate(&[20, 25, 30, 35]).unwrap();
+        assert_eq!(c.breakdown, vec![Duration::minutes(165), Duration::minutes(60), Duration::zero(), Duration::minutes(255)]);
+        assert_eq!(c.values, vec![3300, 1500, 0, 8925]);
+        assert_eq!(c.value, 13725);
+
+        assert_eq!(t.calculate(&[20, 25, 30]), Err(RobotRateError::RateCountMismatch { ranges: 4, rates: 3 }));
+        assert_eq!(t.value(&[20, 25, 30, 35, 40]), Err(RobotRateError::RateCountMismatch { ranges: 4, rates: 5 }));
+    }
+
+    #[test]
+    fn serde_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap(),
+            standard_ranges(),
+        );
+        let c = t.calculate(&[20, 25, 30, 35]).unwrap();
+        let json = serde_json::to_value(&c).unwrap();
+        assert_eq!(json["breakdown"], serde_json::json!([359 * 60, 360 * 60, 60 * 60, 60 * 60]));
+        assert_eq!(json["segments"][0], serde_json::json!({
+            "start": "2021-09-05T22:00:00", "end": "2021-09-05T23:00:00", "status": 2, "kind": "work",
+        }));
+        assert_eq!(json["segments"][3]["status"], serde_json::Value::Null);
+        assert_eq!(serde_json::from_value::<Calculation>(json).unwrap(), c);
+    }
+
+    #[test]
+    fn first_segment_test() {
+        // the shift start is the only point before the end, so the whole shift is its first segment
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-01T21:15:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        let c = t.calculate(&[20, 25]).unwrap();
+        assert_eq!(c.segments, vec![Segment { start: t.start, end: t.end, status: Some(0), kind: SegmentKind::Work }]);
+        assert_eq!(c.value, 60 * 20);
+    }
+
+    #[test]
+    fn date_out_of_range_test() {
+        let t = RobotWorkTime::new(
+            MAX_DATE.and_hms(20, 0, 0),
+            MAX_DATE.and_hms(23, 30, 0),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        assert_eq!(t.calculate(&[20, 25]), Err(RobotRateError::DateOutOfRange));
+
+        // fails before walking the shift, which runs out of dates at the next midnight
+        let t = RobotWorkTime::new(MAX_DATE.and_hms(8, 0, 0), MAX_DATE.and_hms(10, 0, 0), t.time_range);
+        assert_eq!(t.check_representable(), Err(RobotRateError::DateOutOfRange));
+        assert_eq!(t.calculate(&[20, 25]), Err(RobotRateError::DateOutOfRange));
+
+        let t = RobotWorkTime::new(MAX_DATE.pred().pred().and_hms(8, 0, 0), MAX_DATE.pred().pred().and_hms(10, 0, 0), t.time_range);
+        assert_eq!(t.calculate(&[20, 25]).map(|c| c.value), Ok(120 * 20));
+    }
+
+    #[test]
+    fn paid_ratio_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap(),
+            standard_ranges(),
+        );
+        let total = t.shift_duration();
+        assert_eq!(total, Duration::minutes(899));
+        assert_eq!(t.working_duration().unwrap(), total - Duration::minutes(60));
+        assert!((t.paid_ratio(&[20, 25, 30, 35]).unwrap() - (899.0 - 60.0) / 899.0).abs() < 1e-9);
+
+        assert_eq!(t.billed_minutes().unwrap(), 899 - 60);
+
+        // the weekend night is worked from 23:00 to midnight on Sunday, unpaid but billed
+        assert!((t.paid_ratio(&[20, 25, 30, 0]).unwrap() - (899.0 - 60.0 - 60.0) / 899.0).abs() < 1e-9);
+        assert_eq!(t.billed_minutes().unwrap(), 899 - 60);
+    }
+
+    #[test]
+    fn zero_span_test() {
+        let start = NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap();
+        let rates = [20, 25, 30, 35];
+        let empty = RobotWorkTime::new(start, start, standard_ranges());
+        for empty in [empty.clone(), empty.with_minute_rounding(MinuteRounding::Ceil)].iter() {
+            assert_eq!(empty.shift_duration(), Duration::zero());
+            assert_eq!(empty.working_duration().unwrap(), Duration::zero());
+            assert_eq!(empty.billed_minutes().unwrap(), 0);
+            assert!(empty.paid_ratio(&rates).unwrap().abs() < 1e-9);
+            assert_eq!(empty.equivalent_flat_rate(&rates), Ok(0.0));
+            assert!(empty.tiers_touched().unwrap().is_empty());
+            assert_eq!(empty.value(&rates), Ok(0));
+        }
+    }
+
+    #[test]
+    fn hourly_heatmap_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let t = RobotWorkTime::new(at("2021-09-06T06:30:00"), at("2021-09-08T10:45:00"), standard_ranges());
+        let heatmap = t.hourly_heatmap().unwrap();
+        let total = heatmap.iter().flat_map(|hours| hours.iter()).map(|minutes| *minutes as u64).sum::<u64>();
+        assert_eq!(total, t.billed_minutes().unwrap());
+
+        // an hour's break every 8 hours from 14:30 on Monday, the one from 23:30 running into Tuesday
+        let (mon, tue, wed) = (heatmap[0], heatmap[1], heatmap[2]);
+        assert_eq!(mon[..7], [0, 0, 0, 0, 0, 0, 30]);
+        assert_eq!((mon[14], mon[15], mon[16], mon[23]), (30, 30, 60, 30));
+        assert_eq!((tue[0], tue[1], tue[8], tue[9]), (30, 60, 30, 30));
+        assert_eq!((wed[2], wed[3], wed[10], wed[11]), (30, 30, 45, 0));
+        assert!(heatmap[3..].iter().all(|hours| hours.iter().all(|minutes| *minutes == 0)));
+    }
+
+    #[test]
+    fn accrued_value_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T17:00:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [10, 10, 10, 10];
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        assert_eq!(t.value(&rates), Ok(540 * 10));
+        assert_eq!(t.accrued_value(at("2021-09-06T11:00:00"), &rates), Ok(240 * 10));
+        assert_eq!(t.accrued_value(at("2021-09-06T11:30:30"), &rates), Ok(270 * 10));
+        // no charge for the break up to 16:00
+        assert_eq!(t.accrued_value(at("2021-09-06T15:30:00"), &rates), Ok(480 * 10));
+        assert_eq!(t.accrued_value(at("2021-09-06T16:30:00"), &rates), Ok(510 * 10));
+        assert_eq!(t.accrued_value(at("2021-09-06T06:00:00"), &rates), Ok(0));
+        assert_eq!(t.accrued_value(at("2021-09-06T18:00:00"), &rates), Ok(540 * 10));
+
+        // about half at the midpoint of a shift without a break
+        let t = RobotWorkTime::new(t.start, at("2021-09-06T15:00:00"), standard_ranges());
+        assert_eq!(t.accrued_value(at("2021-09-06T11:00:00"), &rates).unwrap() * 2, t.value(&rates).unwrap());
+
+        assert_eq!(t.accrued_value(at("2021-09-06T11:00:00"), &rates[..3]), Err(RobotRateError::RateCountMismatch { ranges: 4, rates: 3 }));
+    }
+
+    #[test]
+    fn cheapest_start_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let rates = [20, 25, 30, 35];
+        // starting before 07:00 bills the night range, and the day range ends at 23:00
+        let window = (at("2021-09-06T04:00:00"), at("2021-09-06T18:00:00"));
+        let cheapest = RobotWorkTime::cheapest_start(Duration::hours(8), window, Duration::hours(1), standard_ranges(), &rates);
+        assert_eq!(cheapest, Ok((at("2021-09-06T07:00:00"), 480 * 20)));
+
+        let cheapest = RobotWorkTime::cheapest_start(Duration::hours(8), window, Duration::zero(), standard_ranges(), &rates);
+        assert_eq!(cheapest, Ok((window.0, 180 * 25 + 300 * 20)));
+    }
+
+    #[test]
+    fn tiers_touched_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-05T23:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            standard_ranges(),
+        );
+        // Sunday night, then Monday night after midnight
+        assert_eq!(t.tiers_touched().unwrap(), vec![1, 3].into_iter().collect());
+    }
+
+    #[test]
+    fn equivalent_flat_rate_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap(),
+            standard_ranges(),
+        );
+        let rate = t.equivalent_flat_rate(&[20, 25, 30, 35]).unwrap();
+        assert!((rate - 13725.0 / 480.0).abs() < 1e-9);
+        assert!(20.0 < rate && rate < 35.0);
+    }
+
+    #[test]
+    fn daily_breakdown_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T15:30:30").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T02:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        let c = t.calculate(&[20, 25]).unwrap();
+        let (first, second) = (NaiveDate::from_ymd(2038, 1, 1), NaiveDate::from_ymd(2038, 1, 2));
+
+        // the night range is worked from 23:00:00 until the break at 23:30:30, and from 00:30:30 on
+        let new_day = c.daily_breakdown();
+        assert_eq!(new_day, vec![
+            (first, vec![Duration::minutes(449), Duration::minutes(30)]),
+            (second, vec![Duration::zero(), Duration::minutes(90)]),
+        ]);
+        let ending_day = c.daily_breakdown_with(Midnight::EndingDay);
+        assert_eq!(ending_day, vec![
+            (first, vec![Duration::minutes(449), Duration::minutes(31)]),
+            (second, vec![Duration::zero(), Duration::minutes(89)]),
+        ]);
+
+        for days in [new_day, ending_day].iter() {
+            let night = days.iter().fold(Duration::zero(), |acc, (_, durations)| acc + durations[1]);
+            assert_eq!(night.num_minutes(), c.breakdown[1].num_minutes());
+        }
+    }
+
+    #[test]
+    fn minute_rounding_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let rates = [20, 25, 30, 35];
+        // 59m30s of day and 30m of night
+        let t = RobotWorkTime::new(at("2021-09-06T22:00:30"), at("2021-09-06T23:30:00"), standard_ranges());
+        assert_eq!(t.value(&rates), Ok(59 * 20 + 30 * 25));
+        assert_eq!(t.clone().with_minute_rounding(MinuteRounding::Round).value(&rates), Ok(60 * 20 + 30 * 25));
+        assert_eq!(t.with_minute_rounding(MinuteRounding::Ceil).value(&rates), Ok(60 * 20 + 30 * 25));
+
+        // 59m20s of day, and a second of night on the next day
+        let t = RobotWorkTime::new(at("2021-09-06T22:00:40"), at("2021-09-06T23:00:01"), standard_ranges());
+        assert_eq!(t.value(&rates), Ok(59 * 20));
+        assert_eq!(t.clone().with_minute_rounding(MinuteRounding::Round).value(&rates), Ok(59 * 20));
+        let t = t.with_minute_rounding(MinuteRounding::Ceil);
+        assert_eq!(t.value(&rates), Ok(60 * 20 + 25));
+        let c = t.calculate(&rates).unwrap();
+        for midnight in [Midnight::NewDay, Midnight::EndingDay].iter() {
+            let days = c.daily_breakdown_with(*midnight);
+            let total = days.iter().fold(vec![Duration::zero(); 4], |acc, (_, durations)| acc.iter().zip(durations).map(|(a, d)| *a + *d).collect());
+            assert_eq!(&total[..2], &[Duration::minutes(60), Duration::minutes(1)]);
+        }
+        assert_eq!(t.with_max_daily_value(100_000).value(&rates), Ok(60 * 20 + 25));
+    }
+
+    #[test]
+    fn breakdown_hours_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T14:59:30").unwrap(),
+            standard_ranges(),
+        );
+        let c = t.calculate(&[20, 25, 30, 35]).unwrap();
+        assert_eq!(c.breakdown[0].num_minutes(), 479);
+        let hours = c.breakdown_hours();
+        assert!((hours[0] - 7.9917).abs() < 1e-3);
+        assert!(hours[1..].iter().all(|h| h.abs() < 1e-9));
+
+        let t = RobotWorkTime::new(t.start, NaiveDateTime::from_str("2021-09-06T14:59:00").unwrap(), standard_ranges());
+        assert!((t.calculate(&[20, 25, 30, 35]).unwrap().breakdown_hours()[0] - 7.983).abs() < 1e-3);
+    }
+
+    #[test]
+    fn day_start_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:00:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        ).with_max_daily_value(5000);
+        assert_eq!(t.calculate(&[20, 25]).unwrap().daily_breakdown().len(), 2);
+        assert_eq!(t.value(&[20, 25]), Ok(5000 + 5000));
+
+        // the business day from 06:00 takes in the whole night
+        let t = t.with_day_start(NaiveTime::from_hms(6, 0, 0));
+        let c = t.calculate(&[20, 25]).unwrap();
+        assert_eq!(c.daily_breakdown(), vec![
+            (NaiveDate::from_ymd(2038, 1, 1), vec![Duration::minutes(180), Duration::minutes(300)]),
+        ]);
+        assert_eq!(c.value, 5000);
+    }
+
+    #[test]
+    fn line_items_test() {
+        // an 18-hour night stint, with a break from 03:00 to 04:00
+        let t = RobotWorkTime::builder()
+            .shift(NaiveDateTime::from_str("2021-09-06T18:00:00").unwrap(), NaiveDateTime::from_str("2021-09-07T12:00:00").unwrap())
+            .add_range(TimeRange::new((NaiveTime::from_hms(12, 0, 0), NaiveTime::from_hms(18, 0, 0)), all_week()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(18, 0, 0), NaiveTime::from_hms(12, 0, 0)), all_week()))
+            .break_policy(Duration::hours(9), Duration::hours(1))
+            .build()
+            .unwrap();
+        let c = t.calculate(&[20, 25]).unwrap();
+        assert_eq!(c.segments.iter().filter(|segment| segment.status == Some(1)).count(), 3);
+        assert_eq!(c.line_items(), vec![LineItem {
+            status: 1,
+            start: t.start,
+            end: t.end,
+            duration: Duration::hours(17),
+            break_minutes_excluded: 60,
+        }]);
+    }
+
+    #[test]
+    fn cost_of_extra_break_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [20, 25, 30, 35];
+        let at = NaiveDateTime::from_str("2038-01-01T21:00:00").unwrap();
+        assert_eq!(t.cost_of_extra_break(at, Duration::minutes(30), &rates), Ok(-30 * 20));
+
+        // from 22:45 to 23:15, half in the day and half in the night
+        let at = NaiveDateTime::from_str("2038-01-01T22:45:00").unwrap();
+        assert_eq!(t.cost_of_extra_break(at, Duration::minutes(30), &rates), Ok(-15 * 20 - 15 * 25));
+    }
+
+    #[test]
+    fn saving_of_moving_break_test() {
+        // the break falls from 23:00 to 00:00, in the night tier
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T15:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-07T02:00:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [20, 25, 30, 35];
+        let at = NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap();
+        // resting in the day tier instead means working an hour more in the night tier
+        assert_eq!(t.saving_of_moving_break(at, Duration::hours(-1), &rates), Ok(60 * 20 - 60 * 25));
+        assert_eq!(t.saving_of_moving_break(at, Duration::minutes(-30), &rates), Ok(30 * 20 - 30 * 25));
+        assert_eq!(t.saving_of_moving_break(at, Duration::hours(1), &rates), Ok(0));
+        assert_eq!(t.saving_of_moving_break(at + Duration::minutes(1), Duration::hours(-1), &rates), Ok(0));
+
+        // and the other way round
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T14:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-07T02:00:00").unwrap(),
+            standard_ranges(),
+        );
+        let at = NaiveDateTime::from_str("2021-09-06T22:00:00").unwrap();
+        assert_eq!(t.saving_of_moving_break(at, Duration::hours(1), &rates), Ok(60 * 25 - 60 * 20));
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "ends before it starts"))]
+    fn negative_segment_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T12:00:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [20, 25, 30, 35];
+        let mut segments = t.segments().unwrap();
+        let expected = t.bill(&rates, segments.clone()).unwrap().value;
+        // a work segment running backwards, as a non-monotonic walk would pair it
+        let last = *segments.last().unwrap();
+        segments.insert(segments.len() - 1, Segment { start: last.end, end: last.start, ..last });
+        let c = t.bill(&rates, segments).unwrap();
+        assert_eq!(c.value, expected);
+        assert!(c.breakdown.iter().all(|d| *d >= Duration::zero()));
+    }
+
+    #[test]
+    fn rate_table_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap(),
+            standard_ranges(),
+        );
+        assert_eq!(t.total_value(), Err(RobotRateError::MissingRateTable));
+        let t = t.with_rate_table(vec![20, 25, 30, 35]);
+        assert_eq!(t.total_value(), t.value(&[20, 25, 30, 35]));
+        assert_eq!(t.total_calculation(), t.calculate(&[20, 25, 30, 35]));
+        assert_eq!(t.with_rate_table(vec![20]).total_value(), Err(RobotRateError::RateCountMismatch { ranges: 4, rates: 1 }));
+    }
+
+    #[test]
+    fn offset_ranges_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let rates = [20, 25, 30, 35];
+        let first_two_hours = vec![OffsetRange { from_offset: Duration::zero(), to_offset: Duration::hours(2), value: 50 }];
+        // Monday morning, Monday night and Saturday afternoon
+        for (start, rest) in [("2021-09-06T10:00:00", 120 * 20), ("2021-09-06T22:00:00", 120 * 25), ("2021-09-11T13:30:00", 120 * 30)].iter() {
+            let t = RobotWorkTime::new(at(start), at(start) + Duration::hours(4), standard_ranges());
+            let overlay = t.clone().with_offset_ranges(first_two_hours.clone(), OffsetMode::Overlay);
+            assert_eq!(overlay.value(&rates), Ok(120 * 50 + rest));
+            let replace = t.with_offset_ranges(first_two_hours.clone(), OffsetMode::Replace);
+            assert_eq!(replace.value(&rates), Ok(120 * 50));
+        }
+
+        // tasks apply over offset ranges, whichever is set first
+        let t = RobotWorkTime::new(at("2021-09-06T10:00:00"), at("2021-09-06T14:00:00"), standard_ranges());
+        let task = vec![((at("2021-09-06T11:00:00"), at("2021-09-06T13:00:00")), 60)];
+        let expected = Ok(60 * 50 + 120 * 60 + 60 * 20);
+        assert_eq!(t.clone().with_offset_ranges(first_two_hours.clone(), OffsetMode::Overlay).with_task_overrides(task.clone()).value(&rates), expected);
+        assert_eq!(t.clone().with_task_overrides(task).with_offset_ranges(first_two_hours.clone(), OffsetMode::Overlay).value(&rates), expected);
+
+        // offset rates are quoted per the rate unit too
+        let per_hour = t.clone().with_offset_ranges(vec![OffsetRange { value: 3000, ..first_two_hours[0] }], OffsetMode::Overlay).with_rate_unit(RateUnit::PerHour);
+        assert_eq!(per_hour.value(&[1200, 1500, 1800, 2100]), Ok(120 * 50 + 120 * 20));
+        // under Replace, a ramped range bills nothing outside the offset ranges
+        let ramped = t.with_ramp(0, RateRamp { start_value: 20, end_value: 30 });
+        assert_eq!(ramped.with_offset_ranges(first_two_hours.clone(), OffsetMode::Replace).value(&rates), Ok(120 * 50));
+
+        // the overrun of a soft end is billed at the overtime rate, and only it past the offset ranges under Replace
+        let t = RobotWorkTime::new(at("2021-09-06T21:00:00"), at("2021-09-06T22:50:00"), standard_ranges())
+            .with_offset_ranges(vec![OffsetRange { to_offset: Duration::hours(1), ..first_two_hours[0] }], OffsetMode::Replace)
+            .with_soft_end(Duration::minutes(30), 40);
+        assert_eq!(t.value(&rates), Ok(60 * 50 + 10 * 40));
+    }
+
+    #[test]
+    fn task_overrides_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let rates = [20, 25, 30, 35];
+        let t = RobotWorkTime::new(at("2021-09-06T07:00:00"), at("2021-09-06T12:00:00"), standard_ranges())
+            .with_task_overrides(vec![((at("2021-09-06T09:00:00"), at("2021-09-06T10:00:00")), 50)]);
+        let c = t.calculate(&rates).unwrap();
+        assert_eq!(c.value, 240 * 20 + 60 * 50);
+        assert_eq!(c.breakdown[0], Duration::hours(5));
+
+        // the break from 15:00 to 16:00 stays unbilled within the task
+        let t = RobotWorkTime::new(at("2021-09-06T07:00:00"), at("2021-09-06T17:00:00"), standard_ranges())
+            .with_task_overrides(vec![((at("2021-09-06T14:30:00"), at("2021-09-06T15:30:00")), 50)]);
+        assert_eq!(t.value(&rates), Ok(510 * 20 + 30 * 50));
+        assert_eq!(t.clone().with_max_daily_value(100_000).value(&rates), Ok(510 * 20 + 30 * 50));
+        assert_eq!(t.with_break_mode(BreakMode::FractionOfInterruptedTier(0.5)).value(&rates), Ok(510 * 20 + 30 * 50 + 60 * 20 / 2));
+    }
+
+    #[test]
+    fn validate_against_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [20, 25, 30, 35];
+        assert_eq!(t.validate_against(&t, &rates, &rates), Ok(BillingDiff {
+            breakdown: vec![Duration::zero(); 4],
+            values: vec![0; 4],
+            value: 0,
+        }));
+
+        // the weekday night starts at 22:30 instead
+        let mut tweaked = t.clone();
+        let ranges = Arc::make_mut(&mut tweaked.time_range);
+        ranges[0] = ranges[0].with_end(NaiveTime::from_hms(22, 30, 0));
+        ranges[1] = ranges[1].with_start(NaiveTime::from_hms(22, 30, 0));
+        assert_eq!(t.validate_against(&tweaked, &rates, &rates), Ok(BillingDiff {
+            breakdown: vec![Duration::minutes(-30), Duration::minutes(30), Duration::zero(), Duration::zero()],
+            values: vec![-600, 750, 0, 0],
+            value: 150,
+        }));
+
+        // the overrun to 04:45 is billed at the overtime rate of either, or in the Saturday night range
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T22:00:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap(),
+            standard_ranges(),
+        );
+        let soft = t.clone().with_soft_end(Duration::minutes(30), 40);
+        let diff = |t: &RobotWorkTime, other: &RobotWorkTime| t.validate_against(other, &rates, &rates).unwrap().value;
+        assert_eq!(diff(&soft, &t.clone().with_soft_end(Duration::minutes(30), 50)), 30 * (50 - 40));
+        assert_eq!(diff(&soft, &t), 30 * (35 - 40));
+        // a later shift has no overrun, whatever the soft end of the other
+        let later = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-02T10:00:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T12:00:00").unwrap(),
+            standard_ranges(),
+        );
+        assert_eq!(diff(&later, &soft), 0);
+        assert_eq!(diff(&later.clone().with_inclusive_end(), &t.clone().with_inclusive_end()), 0);
+    }
+
+    #[test]
+    fn streak_multiplier_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T19:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        assert_eq!(t.value(&[10, 10]), Ok(11 * 600));
+
+        // hours 1-4 at 1.0, hours 5-8 at 1.2, and after the break at 15:00 from 1.0 again
+        let t = t.with_streak_multipliers(vec![(Duration::hours(4), 1.2)]);
+        assert_eq!(t.value(&[10, 10]), Ok(4 * 600 + 4 * 720 + 3 * 600));
+        assert_eq!(t.with_max_daily_value(u64::MAX).value(&[10, 10]), Ok(4 * 600 + 4 * 720 + 3 * 600));
+
+        // the streak from 09:00 runs on past the boundary at 12:00 into the range at the same rate
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T09:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T15:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(12, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(12, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        ).with_streak_multipliers(vec![(Duration::hours(4), 1.5)]);
+        let c = t.calculate(&[10, 10]).unwrap();
+        assert_eq!(c.values, vec![180 * 10, 60 * 10 + 120 * 15]);
+        assert_eq!(c.value, 240 * 10 + 120 * 15);
+    }
+
+    #[test]
+    fn break_mode_test() {
+        let rates = [20, 25, 30, 35];
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        // the break from midnight interrupts the night range
+        let t = RobotWorkTime::new(at("2021-09-06T16:00:00"), at("2021-09-07T02:00:00"), standard_ranges());
+        assert_eq!(t.value(&rates), Ok(420 * 20 + 120 * 25));
+        let t = t.with_break_mode(BreakMode::FractionOfInterruptedTier(0.5));
+        assert_eq!(t.value(&rates), Ok(420 * 20 + 120 * 25 + 60 * 25 / 2));
+        assert_eq!(t.with_max_daily_value(u64::MAX).value(&rates), Ok(420 * 20 + 120 * 25 + 60 * 25 / 2));
+
+        // the break from 22:30 runs into the night range, but is billed at the day range it interrupts
+        let t = RobotWorkTime::new(at("2021-09-06T14:30:00"), at("2021-09-07T00:30:00"), standard_ranges())
+            .with_break_mode(BreakMode::FractionOfInterruptedTier(0.5));
+        let c = t.calculate(&rates).unwrap();
+        assert_eq!(c.values, vec![480 * 20 + 60 * 20 / 2, 60 * 25, 0, 0]);
+        assert_eq!(c.breakdown[0], Duration::minutes(480));
+    }
+
+    #[test]
+    fn rate_change_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-04T22:00:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-05T05:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        assert_eq!(t.value(&[20, 25]), Ok(60 * 20 + 360 * 25));
+
+        // the night rate goes up at 02:00
+        let changed = t.with_rate_change(NaiveDateTime::from_str("2038-01-05T02:00:00").unwrap(), vec![20, 40]);
+        let c = changed.calculate(&[20, 25]).unwrap();
+        assert_eq!(c.breakdown, vec![Duration::minutes(60), Duration::minutes(360)]);
+        assert_eq!(c.values, vec![60 * 20, 180 * 25 + 180 * 40]);
+        assert_eq!(changed.clone().with_max_daily_value(u64::MAX).value(&[20, 25]), Ok(c.value));
+
+        assert_eq!(
+            changed.with_rate_change(NaiveDateTime::from_str("2038-01-05T03:00:00").unwrap(), vec![20]).value(&[20, 25]),
+            Err(RobotRateError::RateCountMismatch { ranges: 2, rates: 1 }),
+        );
+    }
+
+    #[test]
+    fn max_daily_value_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap(),
+            NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [20, 25, 30, 350];
+        // Friday is billed 3300 + 1500, and Saturday night 255 minutes at 350
+        assert_eq!(t.value(&rates), Ok(4800 + 89250));
+
+        let capped = t.clone().with_max_daily_value(10000);
+        let c = capped.calculate(&rates).unwrap();
+        assert_eq!(c.value, 4800 + 10000);
+        assert_eq!(c.values, vec![3300, 1500, 0, 89250]);
+        assert_eq!(t.with_max_daily_value(1000).value(&rates), Ok(2000));
+    }
+
+    #[test]
+    fn merge_rates_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-10T00:01:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-12T00:30:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [20, 25, 30, 35];
+        let c = t.calculate(&rates).unwrap();
+        let groups = t.merge_rates(&rates, &["day", "night", "day", "night"]).unwrap();
+
+        assert_eq!(groups.iter().map(|g| g.name.as_str()).collect::<Vec<_>>(), vec!["day", "night"]);
+        assert_eq!(groups[0].duration, c.breakdown[0] + c.breakdown[2]);
+        assert_eq!(groups[0].value, c.values[0] + c.values[2]);
+        assert_eq!(groups[1].value, c.values[1] + c.values[3]);
+        assert_eq!(groups[0].value + groups[1].value, c.value);
+
+        assert_eq!(t.merge_rates(&rates, &["day", "night"]), Err(RobotRateError::GroupCountMismatch { ranges: 4, groups: 2 }));
+        assert_eq!(c.grouped_breakdown(&["day", "night", "day", "night"]).unwrap(), groups);
+        assert_eq!(c.grouped_breakdown(&["day", "night", "day", "night", "day"]), Err(RobotRateError::GroupCountMismatch { ranges: 4, groups: 5 }));
+    }
+}
@@ -0,0 +1,47 @@
+use std::fmt;
+
+use chrono::{NaiveTime, Weekday};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RobotRateError {
+    /// The number of rates does not match the number of time ranges they price.
+    RateCountMismatch { ranges: usize, rates: usize },
+    /// The number of report groups does not match the number of time ranges they group.
+    GroupCountMismatch { ranges: usize, groups: usize },
+    /// Walking the shift ran past the dates chrono can represent.
+    DateOutOfRange,
+    /// A `RobotWorkTimeBuilder` was built without a shift.
+    MissingShift,
+    /// A `RobotWorkTime` was billed at its own rates without a rate table.
+    MissingRateTable,
+    /// The work duration of a break policy is not positive, its rest is negative or not finite, or either is not in
+    /// whole milliseconds.
+    InvalidBreakPolicy,
+    /// No time range covers this time of the week.
+    UncoveredTime { weekday: Weekday, time: NaiveTime },
+    /// A weekly grid CSV is malformed at this line, counting from 1.
+    InvalidGrid { line: usize },
+    /// A part of a compact one-line schema, e.g. `shift`, is missing or malformed.
+    InvalidCompactSchema { part: String },
+    /// A time of a json schema, at this path, e.g. `roboRate.standardNight.end`, is not a valid time.
+    InvalidTime { path: String, value: String },
+}
+
+impl fmt::Display for RobotRateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RobotRateError::RateCountMismatch { ranges, rates } => write!(f, "{} rates given for {} time ranges", rates, ranges),
+            RobotRateError::GroupCountMismatch { ranges, groups } => write!(f, "{} report groups given for {} time ranges", groups, ranges),
+            RobotRateError::DateOutOfRange => write!(f, "the shift is too close to the limits of representable dates"),
+            RobotRateError::MissingShift => write!(f, "no shift given"),
+            RobotRateError::MissingRateTable => write!(f, "no rate table given"),
+            RobotRateError::InvalidBreakPolicy => write!(f, "the break policy must work for a positive duration"),
+            RobotRateError::UncoveredTime { weekday, time } => write!(f, "no time range covers {} {}", weekday, time),
+            RobotRateError::InvalidGrid { line } => write!(f, "invalid grid at line {}", line),
+            RobotRateError::InvalidCompactSchema { part } => write!(f, "missing or invalid {} in the compact schema", part),
+            RobotRateError::InvalidTime { path, value } => write!(f, "{} is not a valid time: '{}'", path, value),
+        }
+    }
+}
+
+impl std::error::Error for RobotRateError {}
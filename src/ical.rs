@@ -0,0 +1,66 @@
+use chrono::NaiveDateTime;
+
+use crate::{RobotRateError, RobotWorkTime, SegmentKind};
+
+const FORMAT: &str = "%Y%m%dT%H%M%S";
+
+impl RobotWorkTime {
+    /// Exports the segments of the shift as an iCalendar (RFC 5545), one event each, in floating local time.
+    pub fn to_ical(&self) -> Result<String, RobotRateError> {
+        let mut lines = vec![
+            "BEGIN:VCALENDAR".to_string(),
+            "VERSION:2.0".to_string(),
+            "PRODID:-//robot-rate-calculator//EN".to_string(),
+        ];
+        for (idx, segment) in self.segments()?.iter().enumerate() {
+            let summary = match (segment.kind, segment.status) {
+                (SegmentKind::Work, Some(tier)) => format!("Work (tier {})", tier),
+                (SegmentKind::Work, None) | (SegmentKind::Break, _) => "Break".to_string(),
+                (SegmentKind::Maintenance, _) => "Maintenance".to_string(),
+                (SegmentKind::OffDuty, _) => "Off duty".to_string(),
+            };
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}-{}@robot-rate-calculator", format(self.start), idx));
+            // DTSTAMP must be in UTC, which the naive times are taken to be here
+            lines.push(format!("DTSTAMP:{}Z", format(self.start)));
+            lines.push(format!("DTSTART:{}", format(segment.start)));
+            lines.push(format!("DTEND:{}", format(segment.end)));
+            lines.push(format!("SUMMARY:{}", summary));
+            lines.push("END:VEVENT".to_string());
+        }
+        lines.push("END:VCALENDAR".to_string());
+        Ok(lines.iter().map(|line| format!("{}\r\n", line)).collect())
+    }
+}
+
+fn format(datetime: NaiveDateTime) -> String {
+    datetime.format(FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::standard_ranges;
+
+    #[test]
+    fn to_ical_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap(),
+            standard_ranges(),
+        );
+        let ical = t.to_ical().unwrap();
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ical.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ical.matches("BEGIN:VEVENT\r\n").count(), t.segments().unwrap().len());
+        assert_eq!(ical.matches("END:VEVENT\r\n").count(), t.segments().unwrap().len());
+        assert!(ical.contains("DTSTART:20210905T220000\r\nDTEND:20210905T230000\r\nSUMMARY:Work (tier 2)\r\n"));
+        assert!(ical.contains("DTSTART:20210906T060000\r\nDTEND:20210906T070000\r\nSUMMARY:Break\r\n"));
+        assert!(ical.lines().filter(|line| line.starts_with("DTSTART:") || line.starts_with("DTEND:")).all(|line| {
+            let (_, value) = line.split_once(':').unwrap();
+            NaiveDateTime::parse_from_str(value, FORMAT).is_ok()
+        }));
+    }
+}
@@ -1,40 +1,257 @@
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::iter::FusedIterator;
+use std::sync::Arc;
 
-use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday};
 
+use schema::RateRamp;
+
+pub use builder::{BreakPolicy, RobotWorkTimeBuilder};
+pub use calculation::{BillingDiff, Calculation, LineItem, Midnight, MinuteRounding, RateUnit, ReportGroup};
+pub use error::RobotRateError;
+pub use schedule::{BillingPolicy, Schedule};
+pub use segment::{CurrentStatus, InstantExplanation, RangeMatch, Segment, SegmentKind};
+
+#[cfg(feature = "binary")]
+pub mod binary;
+mod builder;
+mod calculation;
+mod error;
+mod ical;
+pub mod presets;
+#[cfg(feature = "proto")]
+pub mod proto;
+mod schedule;
 pub mod schema;
+mod segment;
+pub mod validate;
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct TimeRange {
     start: NaiveTime,
     end: NaiveTime,
     valid_weekdays: HashSet<Weekday>,
+    priority: i32,
+    holidays: BTreeMap<NaiveDate, bool>,
 }
 
 impl TimeRange {
     pub fn new(range: (NaiveTime, NaiveTime), valid_weekdays: impl Iterator<Item=Weekday>) -> Self {
+        Self::from_set(range, valid_weekdays.collect::<HashSet<_>>())
+    }
+
+    /// Like `new`, but takes an existing set of weekdays as is.
+    pub fn from_set(range: (NaiveTime, NaiveTime), valid_weekdays: HashSet<Weekday>) -> Self {
         Self {
             start: range.0,
             end: range.1,
-            valid_weekdays: valid_weekdays.into_iter().collect::<HashSet<_>>(),
+            valid_weekdays,
+            priority: 0,
+            holidays: BTreeMap::new(),
         }
     }
 
+    /// Where ranges overlap, the one with the highest priority applies. Ties go to the range defined last.
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// On each of `holidays`, the range applies if `applies`, whatever the weekday.
+    pub fn with_holidays(mut self, holidays: &[NaiveDate], applies: bool) -> Self {
+        self.holidays.extend(holidays.iter().map(|d| (*d, applies)));
+        self
+    }
+
+    /// Whether the range applies on the calendar day `d`, see `with_holidays`.
+    fn applies_on(&self, d: NaiveDate) -> bool {
+        self.holidays.get(&d).copied().unwrap_or_else(|| self.valid_weekdays.contains(&d.weekday()))
+    }
+
+    /// A copy of the range starting at `start`. Moving the start past the end turns a range into an overnight one,
+    /// and the other way round.
+    pub fn with_start(&self, start: NaiveTime) -> Self {
+        Self { start, ..self.clone() }
+    }
+
+    /// A copy of the range ending at `end`, see `with_start`.
+    pub fn with_end(&self, end: NaiveTime) -> Self {
+        Self { end, ..self.clone() }
+    }
+
+    /// How many days of the week the range applies on, holidays aside.
+    pub fn weekday_count(&self) -> usize {
+        self.valid_weekdays.len()
+    }
+
+    /// Hours covered by one occurrence of the range, from its start to its end, which for an overnight range
+    /// straddles two days.
+    pub fn covered_hours_per_day(&self) -> f64 {
+        let seconds = (self.end - self.start).num_seconds();
+        let seconds = if seconds < 0 { seconds + 24 * 3600 } else { seconds };
+        seconds as f64 / 3600.0
+    }
+
+    /// A range starting and ending at the same time covers nothing.
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether the range covers `datetime`, on a valid weekday. Ranges are start-inclusive and end-exclusive,
+    /// so where one range ends and the next starts, the boundary instant belongs to the next range. An overnight
+    /// range is cut at midnight, and each part only applies on its own valid weekday.
     pub fn contains(&self, datetime: NaiveDateTime) -> bool {
-        if self.valid_weekdays.contains(&datetime.date().weekday()) {
-            let t = datetime.time();
-            if (self.start < self.end && t >= self.start && t < self.end) || (self.start > self.end && (t >= self.start || t < self.end)) {
-                return true;
+        self.applies_on(datetime.date()) && self.covers_time(datetime.time())
+    }
+
+    /// The range covering what both ranges cover, on their common weekdays and holidays, with the higher priority of
+    /// the two. `None` if they are disjoint. As overnight ranges are cut at midnight, the overlap may fall apart into
+    /// two parts of the day, e.g. of 06:00-23:00 and 22:00-07:00 in the morning and in the evening, and then only
+    /// the longer part is returned, the earlier one on a tie. Parts meeting at midnight make an overnight range.
+    pub fn intersect(&self, other: &TimeRange) -> Option<TimeRange> {
+        let valid_weekdays = self.valid_weekdays.intersection(&other.valid_weekdays).copied().collect::<HashSet<_>>();
+        let holidays = self.holidays.keys().chain(other.holidays.keys())
+            .map(|d| (*d, self.applies_on(*d) && other.applies_on(*d)))
+            .collect::<BTreeMap<_, _>>();
+        if valid_weekdays.is_empty() && !holidays.values().any(|applies| *applies) {
+            return None;
+        }
+
+        let mut parts = self.day_parts().into_iter()
+            .flat_map(|(s1, e1)| other.day_parts().into_iter().map(move |(s2, e2)| (s1.max(s2), e1.min(e2))))
+            .filter(|(s, e)| s < e)
+            .collect::<Vec<_>>();
+        parts.sort_unstable();
+        // (start, end, length) of each part, in seconds of the day, with the parts meeting at midnight joined
+        let mut candidates = parts.iter().map(|&(s, e)| (s, e, e - s)).collect::<Vec<_>>();
+        if candidates.len() > 1 && candidates[0].0 == 0 && candidates[candidates.len() - 1].1 == 24 * 3600 {
+            let (_, end, before) = candidates.remove(0);
+            let last = candidates.last_mut().unwrap();
+            *last = (last.0, end, last.2 + before);
+        }
+        let (start, end, _) = candidates.into_iter().max_by_key(|&(s, _, len)| (len, std::cmp::Reverse(s)))?;
+
+        let time = |seconds: u32| NaiveTime::from_num_seconds_from_midnight(seconds % (24 * 3600), 0);
+        Some(Self {
+            start: time(start),
+            end: time(end),
+            valid_weekdays,
+            priority: self.priority.max(other.priority),
+            holidays,
+        })
+    }
+
+    /// The parts of the day the range covers, in seconds from midnight, an overnight range being cut there.
+    fn day_parts(&self) -> Vec<(u32, u32)> {
+        let (s, e) = (self.start.num_seconds_from_midnight(), self.end.num_seconds_from_midnight());
+        match s.cmp(&e) {
+            Ordering::Less => vec![(s, e)],
+            Ordering::Greater => vec![(0, e), (s, 24 * 3600)],
+            Ordering::Equal => vec![],
+        }
+    }
+
+    /// Whether the range covers the time of day `t` on the days it applies.
+    fn covers_time(&self, t: NaiveTime) -> bool {
+        (self.start < self.end && t >= self.start && t < self.end) || (self.start > self.end && (t >= self.start || t < self.end))
+    }
+
+    /// Returns the whole occurrence of this range (from its start to its end instant) which covers `datetime`.
+    pub fn occurrence_at(&self, datetime: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        if !self.contains(datetime) {
+            return None;
+        }
+        let t = datetime.time();
+        let d = datetime.date();
+        if self.start < self.end {
+            Some((d.and_time(self.start), d.and_time(self.end)))
+        } else if t >= self.start {
+            Some((d.and_time(self.start), d.succ_opt()?.and_time(self.end)))
+        } else {
+            Some((d.pred_opt()?.and_time(self.start), d.and_time(self.end)))
+        }
+    }
+
+    /// Returns the part of this range within the calendar day of `datetime` which covers `datetime`.
+    /// Overnight ranges are cut at midnight, where `TimeRangesIterator` produces a transition as well.
+    pub fn piece_at(&self, datetime: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        if !self.contains(datetime) {
+            return None;
+        }
+        let t = datetime.time();
+        let d = datetime.date();
+        if self.start < self.end {
+            Some((d.and_time(self.start), d.and_time(self.end)))
+        } else if t >= self.start {
+            Some((d.and_time(self.start), d.succ_opt()?.and_hms(0, 0, 0)))
+        } else {
+            Some((d.and_hms(0, 0, 0), d.and_time(self.end)))
+        }
+    }
+
+    /// The pieces (see `piece_at`) of this range on the calendar day `d`.
+    /// A piece ending after the last representable day is left out.
+    fn day_pieces(&self, d: NaiveDate) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        if self.is_empty() || !self.applies_on(d) {
+            vec![]
+        } else if self.start < self.end {
+            vec![(d.and_time(self.start), d.and_time(self.end))]
+        } else {
+            let mut pieces = vec![(d.and_hms(0, 0, 0), d.and_time(self.end))];
+            if let Some(next_day) = d.succ_opt() {
+                pieces.push((d.and_time(self.start), next_day.and_hms(0, 0, 0)));
             }
+            pieces
         }
-        false
     }
 
+    /// Returns the pieces (see `piece_at`) of this range within `[from, to)`, clipped to it.
+    pub fn pieces_between(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let mut pieces = vec![];
+        let mut d = from.date();
+        while d <= to.date() {
+            for (s, e) in self.day_pieces(d) {
+                let (s, e) = (s.max(from), e.min(to));
+                if s < e {
+                    pieces.push((s, e));
+                }
+            }
+            match d.succ_opt() {
+                Some(next_day) => d = next_day,
+                None => break,
+            }
+        }
+        pieces
+    }
+
+    /// The earliest instant strictly after `datetime` at which a piece of this range starts or ends.
+    pub fn next_boundary_after(&self, datetime: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=8).filter_map(|days| datetime.date().checked_add_signed(Duration::days(days)))
+            .flat_map(|d| self.day_pieces(d))
+            .flat_map(|(s, e)| vec![s, e])
+            .filter(|b| *b > datetime)
+            .min()
+    }
+
+    /// The latest instant no later than `datetime` at which a piece of this range starts or ends.
+    pub fn prev_boundary_at(&self, datetime: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=8).filter_map(|days| datetime.date().checked_sub_signed(Duration::days(days)))
+            .flat_map(|d| self.day_pieces(d))
+            .flat_map(|(s, e)| vec![s, e])
+            .filter(|b| *b <= datetime)
+            .max()
+    }
+
+    /// The piece (see `piece_at`) covering `datetime`, from `datetime` on, or else the same time on the next day
+    /// the range applies, so that it starts at `datetime` exactly when the range `contains` it.
     pub fn get_next_range_start_at(&self, datetime: NaiveDateTime) -> Option<(NaiveDateTime, NaiveDateTime)> {
         let t = datetime.time();
         let d = datetime.date();
 
-        let ans = if self.start < self.end {
+        let ans = if self.is_empty() {
+            None
+        } else if self.start < self.end {
             if t >= self.start && t < self.end {
                 Some((datetime, d.and_time(self.end)))
             } else {
@@ -45,7 +262,7 @@ impl TimeRange {
                 None
             } else if t >= self.start {
                 // from datetime to the end of the day
-                Some((datetime, d.and_hms(0, 0, 0) + Duration::days(1)))
+                d.succ_opt().map(|next_day| (datetime, next_day.and_hms(0, 0, 0)))
             } else if t < self.end {
                 // from datetime to the end of this range
                 Some((datetime, d.and_time(self.end)))
@@ -54,112 +271,691 @@ impl TimeRange {
             }
         };
 
-        ans.map(|(mut s, mut e)| {
-            while !self.valid_weekdays.contains(&s.date().weekday()) {
-                s += Duration::days(1);
-                e += Duration::days(1);
+        let (mut s, mut e) = ans?;
+        // past its last holiday, a range applies within a week if at all
+        let horizon = self.holidays.keys().next_back().map_or(0, |h| (*h - d).num_days().max(0)) + 7;
+        for _ in 0..horizon {
+            if self.applies_on(s.date()) {
+                return Some((s, e));
             }
-            (s, e)
-        })
+            s = s.checked_add_signed(Duration::days(1))?;
+            e = e.checked_add_signed(Duration::days(1))?;
+        }
+        None
+    }
+}
+
+/// Ranges are ordered by start, end, weekdays from Monday, priority and holidays, e.g. for `RobotWorkTime::normalize`.
+impl Ord for TimeRange {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = |range: &TimeRange| {
+            let mut weekdays = range.valid_weekdays.iter().map(|d| d.num_days_from_monday()).collect::<Vec<_>>();
+            weekdays.sort_unstable();
+            (range.start, range.end, weekdays, range.priority)
+        };
+        key(self).cmp(&key(other)).then_with(|| self.holidays.cmp(&other.holidays))
+    }
+}
+
+impl PartialOrd for TimeRange {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Returns the index of the time range which covers `datetime`. If several do, the one with the highest priority
+/// wins, and among those the last one.
+fn find_range(time_ranges: &[TimeRange], datetime: NaiveDateTime) -> Option<usize> {
+    time_ranges.iter().enumerate()
+        .filter(|(_, range)| range.contains(datetime))
+        .max_by_key(|(idx, range)| (range.priority, *idx))
+        .map(|(idx, _)| idx)
+}
+
+/// The intervals within `[from, to)` which no time range covers, in chronological order.
+fn uncovered_between(time_ranges: &[TimeRange], from: NaiveDateTime, to: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    let mut ret: Vec<(NaiveDateTime, NaiveDateTime)> = vec![];
+    let mut cur = from;
+    while cur < to {
+        let next = time_ranges.iter()
+            .filter_map(|range| range.next_boundary_after(cur))
+            .min()
+            .map_or(to, |next| next.min(to));
+        if find_range(time_ranges, cur).is_none() {
+            match ret.last_mut() {
+                Some(last) if last.1 == cur => last.1 = next,
+                _ => ret.push((cur, next)),
+            }
+        }
+        cur = next;
     }
+    ret
+}
+
+/// Combines a base set of time ranges with an overlay, e.g. a seasonal surcharge, into a partition of the week in
+/// which no two ranges overlap. Where ranges overlap, the one with the highest priority wins as by `find_range`,
+/// so the overlay wins ties. Each range is paired with the index of the range it comes from, in `base` followed
+/// by `overlay`. Holidays are left out.
+pub fn union(base: &[TimeRange], overlay: &[TimeRange]) -> Vec<(usize, TimeRange)> {
+    let time_ranges = base.iter().chain(overlay).cloned().collect::<Vec<_>>();
+    let midnight = NaiveTime::from_hms(0, 0, 0);
+    let parts = validate::week_walk(&time_ranges).into_iter()
+        .filter_map(|(weekday, start, end, tied)| Some((*tied.last()?, weekday, start, Some(end).filter(|end| *end != midnight))));
+    ranges_from_parts(parts)
+}
+
+/// Builds time ranges from the `(key, weekday, start, end)` parts of days, each paired with its key, where an end
+/// of `None` is midnight. Adjacent parts of the same key merge, a part ending at midnight and one starting the same
+/// weekday of the same key make an overnight range, and weekdays with the same range of a key share a `TimeRange`.
+pub(crate) fn ranges_from_parts(parts: impl IntoIterator<Item=(usize, Weekday, NaiveTime, Option<NaiveTime>)>) -> Vec<(usize, TimeRange)> {
+    let midnight = NaiveTime::from_hms(0, 0, 0);
+    let mut days = vec![vec![]; 7];
+    for (key, weekday, start, end) in parts {
+        days[weekday.num_days_from_monday() as usize].push((key, start, end));
+    }
+    let mut ranges: BTreeMap<(usize, NaiveTime, NaiveTime), HashSet<Weekday>> = BTreeMap::new();
+    for (weekday, mut day) in presets::all_week().zip(days) {
+        day.sort_by_key(|(_, start, _)| *start);
+        let mut runs: Vec<(usize, NaiveTime, Option<NaiveTime>)> = vec![];
+        for (key, start, end) in day {
+            match runs.last_mut() {
+                Some(last) if last.0 == key && last.2 == Some(start) => last.2 = end,
+                _ => runs.push((key, start, end)),
+            }
+        }
+        let last = runs.len().saturating_sub(1);
+        if last > 0 && runs[0].0 == runs[last].0 && runs[0].1 == midnight && runs[last].2.is_none() {
+            let (_, _, end) = runs.remove(0);
+            runs.last_mut().unwrap().2 = end;
+        } else if runs.len() == 1 && runs[0].1 == midnight && runs[0].2.is_none() {
+            // a range from midnight to midnight would be empty
+            let noon = NaiveTime::from_hms(12, 0, 0);
+            runs = vec![(runs[0].0, midnight, Some(noon)), (runs[0].0, noon, None)];
+        }
+        for (key, start, end) in runs {
+            ranges.entry((key, start, end.unwrap_or(midnight))).or_default().insert(weekday);
+        }
+    }
+    ranges.into_iter()
+        .map(|((key, start, end), weekdays)| (key, TimeRange::from_set((start, end), weekdays)))
+        .collect()
 }
 
 /// `BreakIterator` produces a infinite sequence of time points at which the robot need to have a break.
+/// It stops early once the time points are no longer representable.
 #[derive(Eq, PartialEq, Debug, Clone)]
 struct BreakIterator {
     start: NaiveDateTime,
     work_duration: Duration,
     rest_duration: Duration,
+    snap: Option<BreakSnap>,
+    /// Off-duty windows, during which the work clock pauses.
+    off_duty: Vec<TimeRange>,
+}
+
+/// Moves breaks to the nearest boundary of the time ranges, see `RobotWorkTime::with_break_snapping`.
+#[derive(Eq, PartialEq, Debug, Clone)]
+struct BreakSnap {
+    tolerance: Duration,
+    time_ranges: Arc<Vec<TimeRange>>,
+}
+
+impl BreakSnap {
+    /// The boundary nearest to `due` within the tolerance, see `snap_to_boundary`. It must be later than `after`,
+    /// where the robot got back to work.
+    fn snap(&self, after: NaiveDateTime, due: NaiveDateTime) -> NaiveDateTime {
+        snap_to_boundary(&self.time_ranges, due, self.tolerance, Some(after))
+    }
+}
+
+/// The boundary of `time_ranges` nearest to `datetime` within `tolerance`, the earlier one on a tie, or `datetime`
+/// itself if there is none. An earlier boundary must be later than `after`.
+fn snap_to_boundary(time_ranges: &[TimeRange], datetime: NaiveDateTime, tolerance: Duration, after: Option<NaiveDateTime>) -> NaiveDateTime {
+    let prev = time_ranges.iter().filter_map(|range| range.prev_boundary_at(datetime)).max();
+    let next = time_ranges.iter().filter_map(|range| range.next_boundary_after(datetime)).min();
+    vec![prev.filter(|b| after.iter().all(|after| b > after)), next].into_iter()
+        .flatten()
+        .filter(|b| (*b - datetime).num_seconds().abs() <= tolerance.num_seconds())
+        .min_by_key(|b| ((*b - datetime).num_seconds().abs(), *b))
+        .unwrap_or(datetime)
+}
+
+/// Whether `duration` is a whole number of milliseconds.
+pub(crate) fn whole_millis(duration: Duration) -> bool {
+    Duration::milliseconds(duration.num_milliseconds()) == duration
+}
+
+/// The start of the cycle of `work` then `rest` from `anchor` under way at `datetime`, which must not be earlier
+/// than `anchor`. `None` if the cycle is empty or not representable. The cycles are counted in milliseconds, so
+/// whole-millisecond cadences land exactly on their grid.
+fn cycle_start(anchor: NaiveDateTime, work: Duration, rest: Duration, datetime: NaiveDateTime) -> Option<NaiveDateTime> {
+    let cycle = (work + rest).num_milliseconds();
+    let k = (datetime - anchor).num_milliseconds().checked_div(cycle)?;
+    anchor.checked_add_signed(Duration::milliseconds(k * cycle))
+}
+
+impl BreakIterator {
+    /// Breaks of `rest_duration` after every `work_duration` of work from `start`. `None` unless the robot works for
+    /// a positive duration and rests for a non-negative one, as breaks would not move forward otherwise, both in
+    /// whole milliseconds, as the cycles are counted in milliseconds (see `cycle_start`).
+    fn new(start: NaiveDateTime, work_duration: Duration, rest_duration: Duration, snap: Option<BreakSnap>, off_duty: Vec<TimeRange>) -> Option<Self> {
+        if work_duration <= Duration::zero() || rest_duration < Duration::zero() || !whole_millis(work_duration) || !whole_millis(rest_duration) {
+            return None;
+        }
+        Some(Self { start, work_duration, rest_duration, snap, off_duty })
+    }
 }
 
 impl Iterator for BreakIterator {
     type Item = (NaiveDateTime, NaiveDateTime);
 
     fn next(&mut self) -> Option<Self::Item> {
-        let work_end = self.start + self.work_duration;
-        let r = (work_end, work_end + self.rest_duration);
-        self.start = work_end + self.rest_duration;
-        Some(r)
+        let mut work_end = self.start.checked_add_signed(self.work_duration)?;
+        // push the break back by the off-duty time until then, including the off-duty time that adds
+        loop {
+            let paused = windows_between(&self.off_duty, self.start, work_end).iter()
+                .fold(Duration::zero(), |acc, (s, e)| acc + (*e - *s));
+            let due = self.start.checked_add_signed(self.work_duration + paused)?;
+            if due == work_end {
+                break;
+            }
+            work_end = due;
+        }
+        if let Some(snap) = &self.snap {
+            work_end = snap.snap(self.start, work_end);
+        }
+        let rest_end = work_end.checked_add_signed(self.rest_duration)?;
+        self.start = rest_end;
+        Some((work_end, rest_end))
     }
 }
 
-#[derive(Clone)]
+/// The pieces of `windows` within `[from, to)`, sorted and merged.
+fn windows_between(windows: &[TimeRange], from: NaiveDateTime, to: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    merge_windows(windows.iter().flat_map(|window| window.pieces_between(from, to)).collect())
+}
+
+/// Sorts `pieces` and merges those overlapping or touching.
+fn merge_windows(mut pieces: Vec<(NaiveDateTime, NaiveDateTime)>) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+    pieces.sort();
+    let mut merged: Vec<(NaiveDateTime, NaiveDateTime)> = vec![];
+    for (s, e) in pieces {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    merged
+}
+
+/// Where a break begins exactly at a time range transition, which point `RobotWorkTimeIterator` produces there.
+/// Either way the time before belongs to the old range, and the robot resumes work in the new one.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum BreakTie {
+    /// Only the break, the transition being taken when the break ends.
+    BreakFirst,
+    /// The transition and then the break, at the same instant.
+    TransitionFirst,
+}
+
+/// How breaks are billed.
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum BreakMode {
+    /// Breaks are not billed.
+    Unbilled,
+    /// Breaks are billed at this fraction of the flat rate of the time range the break interrupts, i.e. the one
+    /// current at the break start, even if the break runs into another range, unless breaks are split.
+    FractionOfInterruptedTier(f64),
+}
+
+/// A per-minute rate applying from `from_offset` to `to_offset` into the shift, whatever the time of day, e.g. a
+/// surcharge for the first 2 hours of any shift.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct OffsetRange {
+    pub from_offset: Duration,
+    pub to_offset: Duration,
+    pub value: u64,
+}
+
+/// How offset ranges combine with the time ranges, see `RobotWorkTime::with_offset_ranges`.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum OffsetMode {
+    /// The offset ranges apply over the time ranges, which bill the rest of the shift.
+    Overlay,
+    /// Only the offset ranges, task overrides and overtime bill work, the time ranges still placing the transitions
+    /// and breaks.
+    Replace,
+}
+
+#[derive(Debug, Clone)]
 pub struct RobotWorkTime {
     start: NaiveDateTime,
     end: NaiveDateTime,
-    time_range: Vec<TimeRange>,
+    time_range: Arc<Vec<TimeRange>>,
+    work_duration: Duration,
+    rest_duration: Duration,
+    break_anchor: NaiveDateTime,
+    ramps: BTreeMap<usize, RateRamp>,
+    maintenance_windows: Vec<TimeRange>,
+    off_duty_windows: Vec<TimeRange>,
+    max_daily_value: Option<u64>,
+    minute_rounding: MinuteRounding,
+    rate_unit: RateUnit,
+    day_start: NaiveTime,
+    break_snap: Option<Duration>,
+    boundary_tolerance: Option<Duration>,
+    break_tie: BreakTie,
+    split_breaks: bool,
+    auto_advance: bool,
+    inclusive_end: bool,
+    break_mode: BreakMode,
+    streak_multipliers: Vec<(Duration, f64)>,
+    rate_changes: Vec<(NaiveDateTime, Vec<u64>)>,
+    rate_table: Option<Vec<u64>>,
+    task_overrides: Vec<((NaiveDateTime, NaiveDateTime), u64)>,
+    soft_end: Option<(NaiveDateTime, u64)>,
+    offset_ranges: Vec<OffsetRange>,
+    offset_mode: OffsetMode,
+    base_fee: u64,
 }
 
 impl RobotWorkTime {
-    pub fn new(start: NaiveDateTime, end: NaiveDateTime, time_range: Vec<TimeRange>) -> Self {
-        Self { start, end, time_range }
+    /// The robot working from `start` to `end`, billed by `time_range`, which may be shared with other robots, e.g.
+    /// those of a `Schedule`, to save copying them.
+    pub fn new(start: NaiveDateTime, end: NaiveDateTime, time_range: impl Into<Arc<Vec<TimeRange>>>) -> Self {
+        Self {
+            start,
+            end,
+            time_range: time_range.into(),
+            work_duration: Duration::hours(8),
+            rest_duration: Duration::hours(1),
+            break_anchor: start,
+            ramps: BTreeMap::new(),
+            maintenance_windows: vec![],
+            off_duty_windows: vec![],
+            max_daily_value: None,
+            minute_rounding: MinuteRounding::Floor,
+            rate_unit: RateUnit::PerMinute,
+            day_start: NaiveTime::from_hms(0, 0, 0),
+            break_snap: None,
+            boundary_tolerance: None,
+            break_tie: BreakTie::BreakFirst,
+            split_breaks: false,
+            auto_advance: false,
+            inclusive_end: false,
+            break_mode: BreakMode::Unbilled,
+            streak_multipliers: vec![],
+            rate_changes: vec![],
+            rate_table: None,
+            task_overrides: vec![],
+            soft_end: None,
+            offset_ranges: vec![],
+            offset_mode: OffsetMode::Overlay,
+            base_fee: 0,
+        }
+    }
+
+    /// Starts the break cadence at `anchor` rather than at the shift start, e.g. when the robot keeps working
+    /// from an earlier shift. The anchor cannot be later than the shift start.
+    pub fn with_break_anchor(mut self, anchor: NaiveDateTime) -> Self {
+        self.break_anchor = anchor.min(self.start);
+        self
+    }
+
+    /// Treats the shift as closed, `[start, end]`, rather than half-open, so that the minute starting at the shift end
+    /// is billed too, in the tier current then. The shift then runs until a minute after `end`, as time is billed by
+    /// the minute. The shift stays half-open if that minute is past the representable dates.
+    pub fn with_inclusive_end(mut self) -> Self {
+        if !self.inclusive_end {
+            if let Some(end) = self.end.checked_add_signed(Duration::minutes(1)) {
+                self.inclusive_end = true;
+                self.end = end;
+            }
+        }
+        self
+    }
+
+    /// Lets the robot finish its task past the shift end, until the occurrence of the time range current just
+    /// before the end ends, but for at most `max_overrun`. The work in the overrun is billed at the per-minute
+    /// `overtime_rate`, whatever the time range and over task overrides (see `with_task_overrides`). A break due in
+    /// the overrun is still taken.
+    pub fn with_soft_end(mut self, max_overrun: Duration, overtime_rate: u64) -> Self {
+        let window_end = self.end.checked_sub_signed(Duration::seconds(1))
+            .and_then(|last| find_range(&self.time_range, last).and_then(|idx| self.time_range[idx].occurrence_at(last)));
+        let overrun_end = match (window_end, self.end.checked_add_signed(max_overrun)) {
+            (Some((_, window_end)), Some(cap)) => window_end.min(cap),
+            _ => return self,
+        };
+        if overrun_end > self.end && self.end > self.start {
+            self.soft_end = Some((self.end, overtime_rate));
+            self.end = overrun_end;
+        }
+        self
+    }
+
+    /// The same shift moved in time by `by`, together with its break cadence. The time ranges stay on the wall
+    /// clock. Fails if the shift moves past the representable dates.
+    pub fn shift(&self, by: Duration) -> Result<RobotWorkTime, RobotRateError> {
+        let moved = |datetime: NaiveDateTime| datetime.checked_add_signed(by).ok_or(RobotRateError::DateOutOfRange);
+        Ok(RobotWorkTime {
+            start: moved(self.start)?,
+            end: moved(self.end)?,
+            break_anchor: moved(self.break_anchor)?,
+            soft_end: self.soft_end.map(|(end, rate)| moved(end).map(|end| (end, rate))).transpose()?,
+            ..self.clone()
+        })
+    }
+
+    /// Moves each break to the nearest time range boundary within `tolerance` of when it is due, so that breaks
+    /// happen at tier changes. Without a boundary that close, the break happens when due. Either way the next
+    /// break is due a work duration after the robot gets back to work.
+    pub fn with_break_snapping(mut self, tolerance: Duration) -> Self {
+        self.break_snap = Some(tolerance);
+        self
+    }
+
+    /// Treats the shift start and end, and the breaks, as falling on a time range boundary when within `tolerance`
+    /// of it, so that timestamp jitter does not produce spurious short segments. The time ranges must be final.
+    pub fn with_boundary_tolerance(mut self, tolerance: Duration) -> Self {
+        self.start = snap_to_boundary(&self.time_range, self.start, tolerance, None);
+        self.end = snap_to_boundary(&self.time_range, self.end, tolerance, None).max(self.start);
+        self.break_anchor = self.break_anchor.min(self.start);
+        self.boundary_tolerance = Some(tolerance);
+        self
+    }
+
+    /// The tolerance within which breaks are moved to a time range boundary, if any.
+    fn break_snap_tolerance(&self) -> Option<Duration> {
+        match (self.break_snap, self.boundary_tolerance) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Sets which point comes first where a break begins exactly at a time range transition.
+    pub fn with_break_tie(mut self, break_tie: BreakTie) -> Self {
+        self.break_tie = break_tie;
+        self
+    }
+
+    /// Sets how breaks are billed, by default not at all.
+    pub fn with_break_mode(mut self, break_mode: BreakMode) -> Self {
+        self.break_mode = break_mode;
+        self
+    }
+
+    /// Splits breaks at the time range transitions they span, so that each part of a break is attributed to the
+    /// range current at its start, e.g. by `BreakMode`. Work still resumes in the range current at the break end.
+    pub fn with_split_breaks(mut self) -> Self {
+        self.split_breaks = true;
+        self
+    }
+
+    /// Starts billing at the first instant a time range covers, if none covers the shift start, rather than failing
+    /// in `try_into_iter`. The time before, as any time no range covers, is reported as off duty, and the break
+    /// cadence starts after it unless anchored earlier, see `uncovered_lead`.
+    pub fn with_auto_advance(mut self) -> Self {
+        self.auto_advance = true;
+        self
+    }
+
+    /// The time from the shift start to the first instant a time range covers, if none covers the shift start. It
+    /// lasts until the shift end if no time range covers any of the shift.
+    pub fn uncovered_lead(&self) -> Option<(NaiveDateTime, NaiveDateTime)> {
+        uncovered_between(&self.time_range, self.start, self.end).into_iter()
+            .next()
+            .filter(|(s, _)| *s == self.start)
+    }
+
+    /// Takes the robot offline during `windows`. Maintenance takes precedence over breaks, which take precedence
+    /// over time ranges, so maintenance time is never billed. The break cadence itself is not moved by maintenance.
+    pub fn with_maintenance_windows(mut self, windows: Vec<TimeRange>) -> Self {
+        self.maintenance_windows = windows;
+        self
+    }
+
+    /// Takes the robot off duty during `windows`, which are not billed. Unlike maintenance, off-duty time does not
+    /// count toward the work duration before a break, so the next break is pushed back by it. Maintenance takes
+    /// precedence over off-duty time, which takes precedence over breaks.
+    pub fn with_off_duty_windows(mut self, windows: Vec<TimeRange>) -> Self {
+        self.off_duty_windows = windows;
+        self
+    }
+
+    /// Bills the work during each task window at the per-minute rate of the task, whatever the time range, e.g. for
+    /// hazardous material handling. Breaks, maintenance and off-duty time within a task stay unbilled, or billed
+    /// at the interrupted range under `BreakMode::FractionOfInterruptedTier`. Where tasks overlap, the one listed
+    /// last applies. The breakdown still reports task time under its time range.
+    pub fn with_task_overrides(mut self, tasks: Vec<((NaiveDateTime, NaiveDateTime), u64)>) -> Self {
+        self.task_overrides = tasks;
+        self
+    }
+
+    /// Bills the work during `ranges`, measured from the shift start, at their rates whatever the time range, ramped
+    /// ranges included. Task overrides apply over them (see `with_task_overrides`). Under `OffsetMode::Replace` the
+    /// time ranges bill nothing, nor the breaks interrupting them. Where offset ranges overlap, the one listed last
+    /// applies.
+    pub fn with_offset_ranges(mut self, ranges: Vec<OffsetRange>, mode: OffsetMode) -> Self {
+        self.offset_ranges = ranges;
+        self.offset_mode = mode;
+        self
+    }
+
+    /// Attaches the per-minute rates of the time ranges, for `total_value` and `total_calculation`.
+    pub fn with_rate_table(mut self, rates: Vec<u64>) -> Self {
+        self.rate_table = Some(rates);
+        self
+    }
+
+    /// Bills the time ranges at `rates` from `effective` on, until a later change. The rates given to `calculate`
+    /// apply before the first change.
+    pub fn with_rate_change(mut self, effective: NaiveDateTime, rates: Vec<u64>) -> Self {
+        self.rate_changes.push((effective, rates));
+        self.rate_changes.sort_by_key(|(effective, _)| *effective);
+        self
+    }
+
+    /// Multiplies the value of work once a streak of work since the last break reaches a threshold, each entry
+    /// `(after, multiplier)` applying from `after` into the streak until the next one. The streak runs on across the
+    /// boundaries of the time ranges, so that adjacent ranges, e.g. at the same rate, count as one block of work.
+    pub fn with_streak_multipliers(mut self, mut multipliers: Vec<(Duration, f64)>) -> Self {
+        multipliers.sort_by_key(|(after, _)| *after);
+        self.streak_multipliers = multipliers;
+        self
+    }
+
+    /// Caps the value charged per day, as split by `Calculation::daily_breakdown`.
+    pub fn with_max_daily_value(mut self, cap: u64) -> Self {
+        self.max_daily_value = Some(cap);
+        self
+    }
+
+    /// Bills the seconds left over from the whole minutes accumulated at each rate by `rounding` rather than
+    /// dropping them.
+    pub fn with_minute_rounding(mut self, rounding: MinuteRounding) -> Self {
+        self.minute_rounding = rounding;
+        self
+    }
+
+    /// Takes the rates, including those of rate changes, ramps, task overrides, offset ranges and overtime, as quoted
+    /// per `unit` rather than per minute.
+    pub fn with_rate_unit(mut self, unit: RateUnit) -> Self {
+        self.rate_unit = unit;
+        self
     }
 
+    /// Adds a flat `fee` to the value of the shift, e.g. a call-out fee, unless the shift is empty. It is not
+    /// subject to `with_max_daily_value` or `with_rate_unit`.
+    pub fn with_base_fee(mut self, fee: u64) -> Self {
+        self.base_fee = fee;
+        self
+    }
+
+    /// Starts the days of `Calculation::daily_breakdown`, and of `with_max_daily_value`, at `day_start` rather than
+    /// at midnight, e.g. for a business day from 06:00 to 06:00.
+    pub fn with_day_start(mut self, day_start: NaiveTime) -> Self {
+        self.day_start = day_start;
+        self
+    }
+
+    /// Bills the time range at `idx` with a linearly ramping rate instead of its flat rate.
+    pub fn with_ramp(mut self, idx: usize, ramp: RateRamp) -> Self {
+        self.ramps.insert(idx, ramp);
+        self
+    }
+
+    /// The canonical form of the robot, billing the same as with `rates`: its time ranges sorted, and those
+    /// identical to a later one removed, with the rates for them, which it returns, and its ramps and rate changes
+    /// following. Overlapping ranges of the same priority keep their order, as the last one wins between them.
+    pub fn normalize(&self, rates: &[u64]) -> Result<(RobotWorkTime, Vec<u64>), RobotRateError> {
+        let ranges = &self.time_range;
+        if rates.len() != ranges.len() {
+            return Err(RobotRateError::RateCountMismatch { ranges: ranges.len(), rates: rates.len() });
+        }
+        let mut left = (0..ranges.len()).filter(|&i| !ranges[i + 1..].contains(&ranges[i])).collect::<Vec<_>>();
+        let must_precede = |a: usize, b: usize| {
+            a < b && ranges[a].priority == ranges[b].priority && ranges[a].intersect(&ranges[b]).is_some()
+        };
+        // the smallest range not bound to come after another one left, which the first of them never is
+        let mut order = vec![];
+        while let Some(pos) = (0..left.len())
+            .filter(|&p| !left.iter().any(|&other| must_precede(other, left[p])))
+            .min_by(|&p, &q| ranges[left[p]].cmp(&ranges[left[q]])) {
+            order.push(left.remove(pos));
+        }
+
+        let pick = |values: &[u64]| order.iter().filter_map(|&i| values.get(i).copied()).collect::<Vec<_>>();
+        let mut robot = self.clone();
+        robot.time_range = Arc::new(order.iter().map(|&i| ranges[i].clone()).collect());
+        robot.ramps = order.iter().enumerate()
+            .filter_map(|(idx, i)| self.ramps.get(i).map(|ramp| (idx, *ramp)))
+            .collect();
+        robot.rate_changes = self.rate_changes.iter().map(|(effective, changed)| (*effective, pick(changed))).collect();
+        robot.rate_table = self.rate_table.as_ref().map(|table| pick(table));
+        Ok((robot, pick(rates)))
+    }
+
+    /// Walks the time points from `start` without ever ending the shift, for monitoring a robot until it is
+    /// stopped, e.g. with `take_while`.
+    pub fn open_ended(start: NaiveDateTime, time_range: Vec<TimeRange>) -> RobotWorkTimeIterator {
+        let mut iter = Self::new(start, start, time_range).into_iter();
+        iter.end = None;
+        iter
+    }
+
+    /// Walks the time points of the shift, see `try_into_iter`.
+    ///
+    /// Panics if no time range covers the shift start, or on a break cadence without work, which
+    /// `RobotWorkTimeBuilder::build` rules out.
     pub fn into_iter(self) -> RobotWorkTimeIterator {
-        let RobotWorkTime { time_range, start, end: _ } = self;
+        self.try_into_iter().unwrap_or_else(|e| panic!("{}", e))
+    }
 
-        let mut time_ranges_iter = TimeRangesIterator::new(start, time_range).unwrap();
-        let cur = time_ranges_iter.next().unwrap();
-        let break_iter = BreakIterator {
-            start: self.start,
-            work_duration: Duration::hours(8),
-            rest_duration: Duration::hours(1),
+    /// Walks the time points of the shift, at which the robot status changes. Fails if no time range covers the
+    /// shift start, unless advancing to the first covered instant, see `with_auto_advance`, or if the robot does not
+    /// work for a positive duration between breaks.
+    pub fn try_into_iter(self) -> Result<RobotWorkTimeIterator, RobotRateError> {
+        if self.auto_advance {
+            if let Some((start, covered)) = self.uncovered_lead().filter(|(_, covered)| *covered < self.end) {
+                let mut advanced = self;
+                advanced.auto_advance = false;
+                if advanced.break_anchor == start {
+                    advanced.break_anchor = covered;
+                }
+                advanced.start = covered;
+                let mut iter = advanced.try_into_iter()?;
+                // the gap is taken like a break, work resuming in the range current at its end
+                if iter.is_finish || iter.cur.1.is_none() {
+                    iter.cur.0 = start;
+                } else {
+                    iter.breaking = Some((covered, iter.cur.1));
+                    iter.cur = (start, None);
+                }
+                return Ok(iter);
+            }
+        }
+        let break_snap = self.break_snap_tolerance();
+        let RobotWorkTime { time_range, start, end, work_duration, rest_duration, break_anchor, break_tie, split_breaks, off_duty_windows, .. } = self;
+
+        let snap = break_snap.map(|tolerance| BreakSnap { tolerance, time_ranges: time_range.clone() });
+        let mut time_ranges_iter = TimeRangesIterator::new(start, time_range)
+            .ok_or(RobotRateError::UncoveredTime { weekday: start.weekday(), time: start.time() })?;
+        let first = time_ranges_iter.next();
+
+        let mut break_iter = BreakIterator::new(break_anchor, work_duration, rest_duration, snap, off_duty_windows)
+            .ok_or(RobotRateError::InvalidBreakPolicy)?;
+        // skip the break cycles completed before the shift starts, one by one if snapping or off-duty time moves
+        // them off the cadence
+        if break_iter.snap.is_none() && break_iter.off_duty.is_empty() {
+            if let Some(cycle_start) = cycle_start(break_anchor, work_duration, rest_duration, start) {
+                break_iter.start = cycle_start;
+            }
+        }
+        while let Some((_, rest_end)) = break_iter.clone().next() {
+            if rest_end > start {
+                break;
+            }
+            break_iter.next();
+        }
+
+        // the shift may start in the middle of a break
+        let (cur, breaking, is_finish) = match (first, break_iter.clone().next()) {
+            (Some(cur), Some((break_begin, break_end))) if break_begin <= start => {
+                break_iter.next();
+                ((start, None), Some((break_end, cur.1)), false)
+            }
+            (Some(cur), Some(_)) => (cur, None, false),
+            // out of representable time points already
+            _ => ((start, None), None, true),
         };
 
-        RobotWorkTimeIterator {
-            cur: (cur.0, Some(cur.1)),
-            end: self.end,
+        Ok(RobotWorkTimeIterator {
+            cur,
+            end: Some(end),
             time_ranges_iter,
             break_iter,
-            breaking: None,
-            is_finish: false,
-        }
+            breaking,
+            break_tie,
+            split_breaks,
+            is_finish,
+        })
     }
 }
 
 /// `TimeSegmentsIterator` produces a infinite sequence of time points, at which the robot status (and the corresponding rates) changed.
+/// It stops early once the time points are no longer representable.
 #[derive(Eq, PartialEq, Debug, Clone)]
 struct TimeRangesIterator {
-    cur: (NaiveDateTime, usize),
-    time_ranges: Vec<TimeRange>,
+    cur: (NaiveDateTime, Option<usize>),
+    time_ranges: Arc<Vec<TimeRange>>,
 }
 
 impl TimeRangesIterator {
-    pub fn new(start: NaiveDateTime, time_ranges: Vec<TimeRange>) -> Option<Self> {
-        let mut next_idx = None;
-        for (idx, range) in time_ranges.iter().enumerate() {
-            if range.contains(start) {
-                next_idx = Some(idx)
-            }
-        }
+    pub fn new(start: NaiveDateTime, time_ranges: impl Into<Arc<Vec<TimeRange>>>) -> Option<Self> {
+        let time_ranges = time_ranges.into();
+        let next_idx = find_range(&time_ranges, start)?;
         Some(Self {
-            cur: (start, next_idx?),
+            cur: (start, Some(next_idx)),
             time_ranges,
         })
     }
 }
 
 impl Iterator for TimeRangesIterator {
-    type Item = (NaiveDateTime, usize);
+    type Item = (NaiveDateTime, Option<usize>);
 
     fn next(&mut self) -> Option<Self::Item> {
         let ret = self.cur;
         let (date_time, _idx) = self.cur;
 
-        let (_, next_dt) = self.time_ranges.iter()
-            .filter_map(|time_range| {
-                time_range.get_next_range_start_at(date_time)
-            })
-            .min_by_key(|(_, next_dt)| *next_dt)
-            .unwrap();
-
-        let mut next_idx = None;
-        for (idx, range) in self.time_ranges.iter().enumerate() {
-            if range.contains(next_dt) {
-                next_idx = Some(idx)
-            }
-        }
-        let next_idx = next_idx.unwrap();
+        // any range starting or ending may change the status, as ranges can overlap
+        let next_dt = self.time_ranges.iter()
+            .filter_map(|time_range| time_range.next_boundary_after(date_time))
+            .min()?;
+
+        // where several ranges start at `next_dt`, the order of `time_ranges` only matters between those of the same
+        // priority, and then the last one wins, see `find_range`. Past the start, time no range covers has no status.
+        let next_idx = find_range(&self.time_ranges, next_dt);
 
         self.cur = (next_dt, next_idx);
         Some(ret)
@@ -167,55 +963,138 @@ impl Iterator for TimeRangesIterator {
 }
 
 /// `RobotWorkTimeIterator` combines `TimeSegmentsIterator` and `BreakIterator`, and produces a finite sequence of time points.
+/// If the time points run out of the representable range, it stops without producing the shift end.
+/// Without an end (see `RobotWorkTime::open_ended`) it only stops there.
 #[derive(Eq, PartialEq, Debug)]
 pub struct RobotWorkTimeIterator {
     cur: (NaiveDateTime, Option<usize>),
-    end: NaiveDateTime,
+    end: Option<NaiveDateTime>,
     time_ranges_iter: TimeRangesIterator,
     break_iter: BreakIterator,
     breaking: Option<(NaiveDateTime, Option<usize>)>,
+    break_tie: BreakTie,
+    split_breaks: bool,
     is_finish: bool,
 }
 
+impl RobotWorkTimeIterator {
+    fn out_of_range(&mut self) -> Option<(NaiveDateTime, Option<usize>)> {
+        self.is_finish = true;
+        None
+    }
+
+    /// Checks in debug builds that the point after `prev` is later, or at the same instant only where a break
+    /// starts at a transition taken first, or lasts no time.
+    fn assert_monotonic(&self, prev: NaiveDateTime) {
+        let coincident = self.break_tie == BreakTie::TransitionFirst || self.break_iter.rest_duration == Duration::zero();
+        debug_assert!(
+            self.cur.0 > prev || (coincident && self.cur.0 == prev),
+            "time point {} produced after {}", self.cur.0, prev,
+        );
+    }
+}
+
 impl Iterator for RobotWorkTimeIterator {
     type Item = (NaiveDateTime, Option<usize>);
 
     fn next(&mut self) -> Option<Self::Item> {
         let ret = self.cur;
         if self.is_finish { return None; }
-        if ret.0 >= self.end {
-            self.is_finish = true;
-            return Some((self.end, None));
+        match self.end {
+            Some(end) if ret.0 >= end => {
+                self.is_finish = true;
+                return Some((end, None));
+            }
+            _ => {}
         }
 
         if let Some((break_end, mut end_status)) = self.breaking.take() {
-            let mut time_ranges_iter = self.time_ranges_iter.clone();
-            let (next_time_seg, next_status) = time_ranges_iter.next().unwrap();
-            if next_time_seg <= break_end {
-                end_status = Some(next_status);
+            // take every transition during the break, so that work resumes in the range current at its end
+            loop {
+                let mut time_ranges_iter = self.time_ranges_iter.clone();
+                let (next_time_seg, next_status) = match time_ranges_iter.next() {
+                    Some(next) => next,
+                    None => return self.out_of_range(),
+                };
+                if next_time_seg > break_end {
+                    break;
+                }
+                end_status = next_status;
                 self.time_ranges_iter.next();
+                if self.split_breaks && ret.0 < next_time_seg && next_time_seg < break_end {
+                    self.cur = (next_time_seg, None);
+                    self.breaking = Some((break_end, end_status));
+                    self.assert_monotonic(ret.0);
+                    return Some(ret);
+                }
             }
             self.cur = (break_end, end_status);
+            self.assert_monotonic(ret.0);
             return Some(ret);
         }
 
         let mut time_ranges_iter = self.time_ranges_iter.clone();
-        let (next_time_seg, next_status) = time_ranges_iter.next().unwrap();
-
         let mut break_iter = self.break_iter.clone();
-        let (break_begin, break_end) = break_iter.next().unwrap();
+        let ((next_time_seg, next_status), (break_begin, break_end)) = match (time_ranges_iter.next(), break_iter.next()) {
+            (Some(next), Some(next_break)) => (next, next_break),
+            _ => return self.out_of_range(),
+        };
 
-        if next_time_seg < break_begin {
-            self.cur = (next_time_seg, Some(next_status));
+        let transition_first = match self.break_tie {
+            BreakTie::BreakFirst => next_time_seg < break_begin,
+            BreakTie::TransitionFirst => next_time_seg <= break_begin,
+        };
+        if transition_first {
+            self.cur = (next_time_seg, next_status);
             self.time_ranges_iter.next();
         } else {
             self.cur = (break_begin, None);
             self.breaking = Some((break_end, ret.1));
             self.break_iter.next();
         }
+        self.assert_monotonic(ret.0);
 
         Some(ret)
     }
+
+    /// At least one point remains until the iterator finishes, unless it runs out of representable time points.
+    /// At most, each boundary of the time ranges until the end is a transition, and a break starts a work duration
+    /// after the last one, or at one of those boundaries when snapped, with a point at its start and its end.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.is_finish {
+            return (0, Some(0));
+        }
+        let end = match self.end {
+            Some(end) => end,
+            None => return (1, None),
+        };
+        if self.cur.0 >= end {
+            return (1, Some(1));
+        }
+        let span = end - self.cur.0;
+        // a range has at most three boundaries a day, at its start, its end and midnight
+        let boundaries = (span.num_days() as usize + 2) * 3 * self.time_ranges_iter.time_ranges.len();
+        let cycles = (span.num_seconds() / self.break_iter.work_duration.num_seconds().max(1)) as usize + 1;
+        let breaks = if self.break_iter.snap.is_some() { boundaries + cycles } else { cycles };
+        // the current point, the end of an ongoing break and the shift end
+        (1, Some(3 + boundaries + 2 * breaks))
+    }
+}
+
+/// Once finished, at the shift end or out of representable time points, it keeps returning `None`.
+impl FusedIterator for RobotWorkTimeIterator {}
+
+/// Standard bands on weekdays and extra bands on weekends, each a day range from 07:00 to 23:00 and a night range
+/// from 23:00 to 07:00, as the schema builds them.
+#[cfg(test)]
+pub(crate) fn standard_ranges() -> Vec<TimeRange> {
+    use presets::{weekdays, weekend};
+    vec![
+        TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekdays()),
+        TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays()),
+        TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()),
+        TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()),
+    ]
 }
 
 #[cfg(test)]
@@ -223,50 +1102,35 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
-
-    fn weekend() -> impl Iterator<Item=Weekday> {
-        use Weekday::*;
-        vec![Sat, Sun].into_iter()
-    }
-
-    fn weekday() -> impl Iterator<Item=Weekday> {
-        use Weekday::*;
-        vec![Mon, Tue, Wed, Thu, Fri].into_iter()
-    }
+    use super::presets::{all_week, weekdays, weekend};
 
     #[test]
     fn robot_work_time_iter_test() {
         let t = RobotWorkTime::new(
             NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
             NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap(),
-            vec![
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()),
-            ],
+            standard_ranges(),
         );
 
         let mut time_ranges_iter = TimeRangesIterator::new(
             NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
-            vec![
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()),
-            ],
+            standard_ranges(),
         ).unwrap();
         time_ranges_iter.next();
 
         let mut it = RobotWorkTimeIterator {
             cur: (NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(), Some(2)),
-            end: NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap(),
+            end: Some(NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap()),
             break_iter: BreakIterator {
                 start: NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
                 work_duration: Duration::hours(8),
                 rest_duration: Duration::hours(1),
+                snap: None,
+                off_duty: vec![],
             },
             breaking: None,
+            break_tie: BreakTie::BreakFirst,
+            split_breaks: false,
             time_ranges_iter,
             is_finish: false,
         };
@@ -282,17 +1146,79 @@ mod tests {
         assert_eq!(it.next(), None);
     }
 
+    #[test]
+    fn try_into_iter_test() {
+        let start = NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap();
+        let t = RobotWorkTime::new(start, start + Duration::hours(2), vec![]);
+        assert_eq!(t.try_into_iter().unwrap_err(), RobotRateError::UncoveredTime { weekday: Weekday::Mon, time: start.time() });
+
+        let t = RobotWorkTime::new(start, start + Duration::hours(2), standard_ranges());
+        assert_eq!(t.clone().try_into_iter().unwrap().collect::<Vec<_>>(), t.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn auto_advance_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let ranges = vec![TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(22, 0, 0)), all_week())];
+        let t = RobotWorkTime::new(at("2021-09-06T22:30:00"), at("2021-09-07T23:00:00"), ranges);
+        assert_eq!(t.uncovered_lead(), Some((at("2021-09-06T22:30:00"), at("2021-09-07T07:00:00"))));
+        assert!(t.clone().try_into_iter().is_err());
+
+        // the break cadence starts at 07:00, and the time from 22:00 is off duty again
+        let t = t.with_auto_advance();
+        assert_eq!(t.clone().into_iter().collect::<Vec<_>>(), vec![
+            (at("2021-09-06T22:30:00"), None),
+            (at("2021-09-07T07:00:00"), Some(0)),
+            (at("2021-09-07T15:00:00"), None),
+            (at("2021-09-07T16:00:00"), Some(0)),
+            (at("2021-09-07T22:00:00"), None),
+            (at("2021-09-07T23:00:00"), None),
+        ]);
+        let kinds = t.segments().unwrap().into_iter().map(|segment| segment.kind).collect::<Vec<_>>();
+        assert_eq!(kinds, vec![SegmentKind::OffDuty, SegmentKind::Work, SegmentKind::Break, SegmentKind::Work, SegmentKind::OffDuty]);
+        assert_eq!(t.segments().unwrap()[0].end, at("2021-09-07T07:00:00"));
+        assert_eq!(t.calculate(&[10]).unwrap().value, 840 * 10);
+    }
+
+    #[test]
+    fn fused_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T09:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        let mut it = t.into_iter();
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap(), Some(0))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T09:00:00").unwrap(), None)));
+        for _ in 0..5 {
+            assert_eq!(it.next(), None);
+        }
+    }
+
+    #[test]
+    fn open_ended_test() {
+        let start = NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap();
+        let ranges = standard_ranges();
+        let bounded = RobotWorkTime::new(start, NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap(), ranges.clone());
+        assert_eq!(
+            RobotWorkTime::open_ended(start, ranges.clone()).take(5).collect::<Vec<_>>(),
+            bounded.into_iter().take(5).collect::<Vec<_>>(),
+        );
+
+        let week_later = NaiveDateTime::from_str("2021-09-12T22:00:00").unwrap();
+        let last = RobotWorkTime::open_ended(start, ranges).take_while(|(dt, _)| *dt < week_later).last();
+        assert_eq!(last, Some((NaiveDateTime::from_str("2021-09-12T16:00:00").unwrap(), Some(2))));
+    }
+
     #[test]
     fn robot_work_time_iter_test_start_early() {
         let t = RobotWorkTime::new(
             NaiveDateTime::from_str("2021-09-10T00:01:00").unwrap(),
             NaiveDateTime::from_str("2021-09-12T00:30:00").unwrap(),
-            vec![
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()),
-            ],
+            standard_ranges(),
         );
         let mut it = t.into_iter();
 
@@ -322,12 +1248,7 @@ mod tests {
         let t = RobotWorkTime::new(
             NaiveDateTime::from_str("2021-09-10T23:01:00").unwrap(),
             NaiveDateTime::from_str("2021-09-11T12:55:00").unwrap(),
-            vec![
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()),
-            ],
+            standard_ranges(),
         );
         let mut it = t.into_iter();
 
@@ -346,8 +1267,8 @@ mod tests {
             NaiveDateTime::from_str("2021-09-10T23:01:00").unwrap(),
             NaiveDateTime::from_str("2021-09-11T20:55:00").unwrap(),
             vec![
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekday()),
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekdays()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays()),
                 TimeRange::new((NaiveTime::from_hms(3, 0, 0), NaiveTime::from_hms(15, 0, 0)), weekend()),
                 TimeRange::new((NaiveTime::from_hms(15, 0, 0), NaiveTime::from_hms(3, 0, 0)), weekend()),
             ],
@@ -370,49 +1291,316 @@ mod tests {
     fn time_seg_iter_test() {
         let mut it = TimeRangesIterator::new(
             NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
-            vec![
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()),
-            ],
+            standard_ranges(),
         ).unwrap();
 
-        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(), 2)));
-        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-05T23:00:00").unwrap(), 3)));
-        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T00:00:00").unwrap(), 1)));
-        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(), 0)));
-        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), 1)));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(), Some(2))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-05T23:00:00").unwrap(), Some(3))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T00:00:00").unwrap(), Some(1))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(), Some(0))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), Some(1))));
+    }
+
+    #[test]
+    fn time_seg_iter_tie_test() {
+        let ranges = |second_priority| vec![
+            TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(12, 0, 0)), all_week()),
+            TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(10, 0, 0)), all_week()).with_priority(second_priority),
+            TimeRange::new((NaiveTime::from_hms(12, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+        ];
+        let start = NaiveDateTime::from_str("2021-09-06T06:00:00").unwrap();
+        let seven = start + Duration::hours(1);
+        assert_eq!(ranges(0)[0].get_next_range_start_at(seven).map(|r| r.0), Some(seven));
+        assert_eq!(ranges(0)[1].get_next_range_start_at(seven).map(|r| r.0), Some(seven));
+
+        // both start at 07:00, and the one listed last wins
+        let mut it = TimeRangesIterator::new(start, ranges(0)).unwrap();
+        assert_eq!(it.next(), Some((start, Some(2))));
+        assert_eq!(it.next(), Some((seven, Some(1))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T10:00:00").unwrap(), Some(0))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T12:00:00").unwrap(), Some(2))));
+
+        // unless the other has a higher priority, whatever the order
+        let mut it = TimeRangesIterator::new(start, ranges(-1)).unwrap();
+        it.next();
+        assert_eq!(it.next(), Some((seven, Some(0))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T10:00:00").unwrap(), Some(0))));
+    }
+
+    #[test]
+    fn sub_second_cadence_test() {
+        // the anchor lies three cycles of 1.1s and 200ms before the start
+        let start = NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap();
+        let ms = Duration::milliseconds;
+        let mut t = RobotWorkTime::new(start, start + Duration::seconds(2), standard_ranges()).with_break_anchor(start - ms(3500));
+        t.work_duration = ms(500);
+        t.rest_duration = ms(600);
+        let breaks = t.segments().unwrap().into_iter()
+            .filter(|segment| segment.kind == SegmentKind::Break)
+            .map(|segment| (segment.start, segment.end))
+            .collect::<Vec<_>>();
+        assert_eq!(breaks, vec![(start + ms(300), start + ms(900)), (start + ms(1400), start + ms(2000))]);
     }
 
     #[test]
     fn break_iter_test() {
-        let mut it = BreakIterator {
-            start: NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
-            work_duration: Duration::hours(8),
-            rest_duration: Duration::hours(1),
-        };
+        let start = NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap();
+        assert_eq!(BreakIterator::new(start, Duration::zero(), Duration::hours(1), None, vec![]), None);
+        assert_eq!(BreakIterator::new(start, Duration::hours(-1), Duration::hours(1), None, vec![]), None);
+        assert_eq!(BreakIterator::new(start, Duration::hours(8), Duration::seconds(-1), None, vec![]), None);
+        let mut t = RobotWorkTime::new(start, start + Duration::hours(9), standard_ranges());
+        t.work_duration = Duration::zero();
+        assert_eq!(t.try_into_iter().unwrap_err(), RobotRateError::InvalidBreakPolicy);
+        assert_eq!(BreakIterator::new(start, Duration::microseconds(500), Duration::zero(), None, vec![]), None);
+        assert_eq!(BreakIterator::new(start, Duration::hours(8), Duration::microseconds(1500), None, vec![]), None);
+        t.work_duration = Duration::microseconds(500);
+        t.rest_duration = Duration::zero();
+        assert_eq!(t.try_into_iter().unwrap_err(), RobotRateError::InvalidBreakPolicy);
+
+        let mut it = BreakIterator::new(start, Duration::hours(8), Duration::hours(1), None, vec![]).unwrap();
 
         assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T06:00:00").unwrap(), NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap())));
         assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T15:00:00").unwrap(), NaiveDateTime::from_str("2021-09-06T16:00:00").unwrap())));
         assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-07T00:00:00").unwrap(), NaiveDateTime::from_str("2021-09-07T01:00:00").unwrap())));
     }
 
+    #[test]
+    fn break_snapping_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-05T21:45:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T12:00:00").unwrap(),
+            standard_ranges(),
+        );
+
+        // the break is due at 05:45, 1h15m before the day boundary
+        let points = t.clone().with_break_snapping(Duration::hours(2)).into_iter().collect::<Vec<_>>();
+        assert_eq!(points[3..], [
+            (NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap(), Some(0)),
+            (NaiveDateTime::from_str("2021-09-06T12:00:00").unwrap(), None),
+        ]);
+        let points = t.with_break_snapping(Duration::hours(1)).into_iter().collect::<Vec<_>>();
+        assert_eq!(points[3..5], [
+            (NaiveDateTime::from_str("2021-09-06T05:45:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-06T06:45:00").unwrap(), Some(1)),
+        ]);
+    }
+
+    #[test]
+    fn break_tie_test() {
+        // the break is due at 23:00, when the day range ends
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T15:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-07T01:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        assert_eq!(t.clone().into_iter().collect::<Vec<_>>(), vec![
+            (NaiveDateTime::from_str("2021-09-06T15:00:00").unwrap(), Some(0)),
+            (NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-07T00:00:00").unwrap(), Some(1)),
+            (NaiveDateTime::from_str("2021-09-07T01:00:00").unwrap(), None),
+        ]);
+        assert_eq!(t.clone().with_break_tie(BreakTie::TransitionFirst).into_iter().collect::<Vec<_>>(), vec![
+            (NaiveDateTime::from_str("2021-09-06T15:00:00").unwrap(), Some(0)),
+            (NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), Some(1)),
+            (NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-07T00:00:00").unwrap(), Some(1)),
+            (NaiveDateTime::from_str("2021-09-07T01:00:00").unwrap(), None),
+        ]);
+        assert_eq!(t.value(&[20, 25]), t.with_break_tie(BreakTie::TransitionFirst).value(&[20, 25]));
+    }
+
+    #[test]
+    fn inclusive_end_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let open = RobotWorkTime::new(at("2021-09-06T06:00:00"), at("2021-09-06T07:00:00"), standard_ranges());
+        let closed = open.clone().with_inclusive_end().with_inclusive_end();
+
+        // the instant 07:00 bills nothing, unless the shift is closed, and then at the day rate
+        let last = |t: &RobotWorkTime| t.segments().unwrap().last().copied().unwrap();
+        assert_eq!((last(&open).end, last(&open).status), (at("2021-09-06T07:00:00"), Some(1)));
+        assert_eq!(last(&closed), Segment { start: at("2021-09-06T07:00:00"), end: at("2021-09-06T07:01:00"), status: Some(0), kind: SegmentKind::Work });
+        assert_eq!(open.value(&[20, 25, 30, 35]), Ok(60 * 25));
+        assert_eq!(closed.value(&[20, 25, 30, 35]), Ok(60 * 25 + 20));
+
+        let last_minute = chrono::naive::MAX_DATE.and_hms(23, 59, 30);
+        let t = RobotWorkTime::new(last_minute - Duration::hours(1), last_minute, standard_ranges()).with_inclusive_end();
+        assert!(!t.inclusive_end);
+        assert_eq!(t.end, last_minute);
+    }
+
+    #[test]
+    fn soft_end_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let rates = [20, 25, 30, 35];
+        let t = RobotWorkTime::new(at("2021-09-06T15:00:00"), at("2021-09-06T22:50:00"), standard_ranges());
+
+        // the day range ends at 23:00, within the overrun allowed
+        let soft = t.clone().with_soft_end(Duration::minutes(30), 40);
+        assert_eq!(soft.segments().unwrap().last().unwrap().end, at("2021-09-06T23:00:00"));
+        assert_eq!(soft.value(&rates), Ok(470 * 20 + 10 * 40));
+        assert_eq!(soft.calculate(&rates).unwrap().breakdown[0], Duration::minutes(480));
+        // tasks set later keep the overrun at the overtime rate
+        let task = ((at("2021-09-06T16:00:00"), at("2021-09-06T17:00:00")), 50);
+        assert_eq!(soft.clone().with_task_overrides(vec![task]).value(&rates), Ok(410 * 20 + 60 * 50 + 10 * 40));
+        assert_eq!(soft.shift(Duration::hours(-1)).unwrap().value(&rates), Ok(470 * 20 + 10 * 40));
+
+        let capped = t.with_soft_end(Duration::minutes(5), 40);
+        assert_eq!(capped.value(&rates), Ok(470 * 20 + 5 * 40));
+        // ending with the range, there is nothing left to finish
+        let t = RobotWorkTime::new(at("2021-09-06T15:00:00"), at("2021-09-06T23:00:00"), standard_ranges());
+        assert_eq!(t.clone().with_soft_end(Duration::minutes(30), 40).segments().unwrap(), t.segments().unwrap());
+    }
+
+    #[test]
+    fn shift_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T14:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-07T00:00:00").unwrap(),
+            standard_ranges(),
+        );
+        let delayed = t.shift(Duration::hours(2)).unwrap();
+        assert_eq!(delayed.clone().into_iter().collect::<Vec<_>>(), vec![
+            (NaiveDateTime::from_str("2021-09-06T16:00:00").unwrap(), Some(0)),
+            (NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), Some(1)),
+            (NaiveDateTime::from_str("2021-09-07T00:00:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-07T01:00:00").unwrap(), Some(1)),
+            (NaiveDateTime::from_str("2021-09-07T02:00:00").unwrap(), None),
+        ]);
+        assert_eq!(t.value(&[20, 25, 30, 35]), Ok(480 * 20 + 60 * 25));
+        assert_eq!(delayed.value(&[20, 25, 30, 35]), Ok(420 * 20 + 120 * 25));
+        assert_eq!(delayed.shift(Duration::hours(-2)).unwrap().into_iter().collect::<Vec<_>>(), t.clone().into_iter().collect::<Vec<_>>());
+        assert_eq!(t.shift(Duration::days(366 * 300_000)).err(), Some(RobotRateError::DateOutOfRange));
+    }
+
+    #[test]
+    fn split_breaks_test() {
+        // the break from 22:40 to 23:40 spans the transition at 23:00
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T14:40:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-07T00:40:00").unwrap(),
+            standard_ranges(),
+        ).with_split_breaks();
+        assert_eq!(t.clone().into_iter().collect::<Vec<_>>(), vec![
+            (NaiveDateTime::from_str("2021-09-06T14:40:00").unwrap(), Some(0)),
+            (NaiveDateTime::from_str("2021-09-06T22:40:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-06T23:40:00").unwrap(), Some(1)),
+            (NaiveDateTime::from_str("2021-09-07T00:00:00").unwrap(), Some(1)),
+            (NaiveDateTime::from_str("2021-09-07T00:40:00").unwrap(), None),
+        ]);
+        let rates = [20, 25, 30, 35];
+        let at = NaiveDateTime::from_str("2021-09-06T23:30:00").unwrap();
+        assert_eq!(t.active_segment(at).map(|segment| segment.start), NaiveDateTime::from_str("2021-09-06T23:00:00").ok());
+        let billed = t.clone().with_break_mode(BreakMode::FractionOfInterruptedTier(0.5));
+        assert_eq!(billed.value(&rates), Ok(480 * 20 + 60 * 25 + (20 * 20 + 40 * 25) / 2));
+        assert_eq!(t.value(&rates), RobotWorkTime { split_breaks: false, ..t }.value(&rates));
+    }
+
+    #[test]
+    fn monotonic_test() {
+        // over a day of half-hour ranges, a half-hour break every other range, starting and ending at transitions
+        let ranges = (0..48)
+            .map(|k| NaiveTime::from_hms(k / 2, k % 2 * 30, 0))
+            .map(|s| TimeRange::new((s, s + Duration::minutes(30)), all_week()))
+            .collect::<Vec<_>>();
+        let start = NaiveDateTime::from_str("2021-09-06T00:00:00").unwrap();
+        let mut t = RobotWorkTime::new(start, start + Duration::days(1), ranges);
+        t.work_duration = Duration::minutes(30);
+        t.rest_duration = Duration::minutes(30);
+        for break_tie in [BreakTie::BreakFirst, BreakTie::TransitionFirst].iter() {
+            let points = t.clone().with_break_tie(*break_tie).into_iter().collect::<Vec<_>>();
+            assert_eq!(points.last(), Some(&(t.end, None)));
+            assert!(points.windows(2).all(|w| w[0].0 <= w[1].0));
+        }
+    }
+
+    #[test]
+    fn union_test() {
+        // a summer surcharge on weekday afternoons, across the standard day and night at the weekend
+        let overlay = vec![
+            TimeRange::new((NaiveTime::from_hms(12, 0, 0), NaiveTime::from_hms(14, 0, 0)), presets::weekdays()),
+            TimeRange::new((NaiveTime::from_hms(22, 0, 0), NaiveTime::from_hms(1, 0, 0)), presets::single(Weekday::Sat)),
+        ];
+        let (indices, ranges): (Vec<_>, Vec<_>) = union(&standard_ranges(), &overlay).into_iter().unzip();
+        for (datetime, idx) in [
+            ("2021-09-06T11:59:00", 0), ("2021-09-06T12:00:00", 4), ("2021-09-06T13:59:00", 4), ("2021-09-06T14:00:00", 0),
+            ("2021-09-06T23:30:00", 1), ("2021-09-11T12:00:00", 2), ("2021-09-11T22:30:00", 5), ("2021-09-11T23:30:00", 5),
+            ("2021-09-11T00:30:00", 5), ("2021-09-11T01:00:00", 3), ("2021-09-12T00:30:00", 3),
+        ].iter() {
+            let datetime = NaiveDateTime::from_str(datetime).unwrap();
+            assert_eq!(find_range(&ranges, datetime).map(|k| indices[k]), Some(*idx), "at {}", datetime);
+            assert_eq!(ranges.iter().filter(|range| range.contains(datetime)).count(), 1);
+        }
+
+        // unless the base has the higher priority
+        let base = standard_ranges().into_iter().map(|range| range.with_priority(1)).collect::<Vec<_>>();
+        let (indices, ranges): (Vec<_>, Vec<_>) = union(&base, &overlay).into_iter().unzip();
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+        assert_eq!(ranges, standard_ranges());
+    }
+
+    #[test]
+    fn size_hint_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-08T19:00:00").unwrap(),
+            standard_ranges(),
+        );
+        for t in [t.clone(), t.with_break_snapping(Duration::hours(1))].iter() {
+            let mut it = t.clone().into_iter();
+            let mut hints = vec![it.size_hint()];
+            while it.next().is_some() {
+                hints.push(it.size_hint());
+            }
+            assert_eq!(hints.last(), Some(&(0, Some(0))));
+            // hints[k] is taken with hints.len() - 1 - k points remaining
+            for (k, (lower, upper)) in hints.iter().enumerate() {
+                let remaining = hints.len() - 1 - k;
+                assert!(*lower <= remaining && upper.unwrap() >= remaining);
+            }
+        }
+        let open = RobotWorkTime::open_ended(NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(), standard_ranges());
+        assert_eq!(open.size_hint(), (1, None));
+    }
+
+    #[test]
+    fn boundary_tolerance_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T20:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T23:00:01").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()),
+            ],
+        );
+        let segments = t.segments().unwrap();
+        assert_eq!(segments.last().unwrap().duration(), Duration::seconds(1));
+
+        let t = t.with_boundary_tolerance(Duration::seconds(1));
+        assert_eq!(t.end, NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap());
+        assert_eq!(t.segments().unwrap(), vec![Segment {
+            start: t.start,
+            end: t.end,
+            status: Some(0),
+            kind: SegmentKind::Work,
+        }]);
+    }
+
     #[test]
     fn integration_test_2() {
         let t = RobotWorkTime::new(
             NaiveDateTime::from_str("2038-01-11T07:00:00").unwrap(),
             NaiveDateTime::from_str("2038-01-17T19:00:00").unwrap(),
-            vec![
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekday()),
-                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekend()),
-                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekend()),
-            ],
+            standard_ranges(),
         );
-        let s = t.clone().into_iter().zip(std::iter::once((NaiveDateTime::from_str("2038-01-11T07:00:00").unwrap(), None)).chain(t.into_iter())).skip(1)
-            .fold(vec![Duration::zero(); 4], |mut acc, ((e, _), (s, status))| {
-                if let Some(idx) = status {
+        let points = t.into_iter().collect::<Vec<_>>();
+        let s = points.windows(2)
+            .fold(vec![Duration::zero(); 4], |mut acc, window| {
+                if let [(s, Some(idx)), (e, _)] = *window {
                     acc[idx] = acc[idx] + (e - s);
                 }
                 acc
@@ -420,4 +1608,174 @@ mod tests {
         let res = s[0].num_minutes() * 20 + s[1].num_minutes() * 25 + s[2].num_minutes() * 30 + s[3].num_minutes() * 35;
         assert_eq!(res, 202200);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn ramp_value_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-07T07:00:00").unwrap(),
+            standard_ranges(),
+        );
+        let rates = [20, 25, 30, 35];
+        assert_eq!(t.value(&rates).unwrap(), 480 * 25);
+
+        let t = t.with_ramp(1, RateRamp { start_value: 25, end_value: 35 });
+        assert_eq!(t.value(&rates).unwrap(), 480 * 30);
+    }
+
+    #[test]
+    fn contains_boundary_test() {
+        let day = TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekdays());
+        let night = TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays());
+
+        let at_23 = NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap();
+        assert!(night.contains(at_23));
+        assert!(!day.contains(at_23));
+
+        let at_7 = NaiveDateTime::from_str("2021-09-07T07:00:00").unwrap();
+        assert!(day.contains(at_7));
+        assert!(!night.contains(at_7));
+
+        let before_7 = NaiveDateTime::from_str("2021-09-07T06:59:59").unwrap();
+        assert!(night.contains(before_7));
+        assert!(!day.contains(before_7));
+    }
+
+    #[test]
+    fn with_start_test() {
+        let night = TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week());
+        let at = NaiveDateTime::from_str("2021-09-06T22:45:00").unwrap();
+        assert!(!night.contains(at));
+        assert!(night.with_start(NaiveTime::from_hms(22, 30, 0)).contains(at));
+
+        // no longer overnight
+        let morning = night.with_start(NaiveTime::from_hms(5, 0, 0));
+        assert!(morning.contains(NaiveDateTime::from_str("2021-09-06T06:00:00").unwrap()));
+        assert!(!morning.contains(NaiveDateTime::from_str("2021-09-06T23:30:00").unwrap()));
+        assert_eq!(morning.with_end(NaiveTime::from_hms(4, 0, 0)).occurrence_at(at), Some((
+            NaiveDateTime::from_str("2021-09-06T05:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-07T04:00:00").unwrap(),
+        )));
+    }
+
+    #[test]
+    fn normalize_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let shift = (at("2021-09-10T20:00:00"), at("2021-09-11T10:00:00"));
+        let robot = |ranges| RobotWorkTime::new(shift.0, shift.1, ranges);
+        let rates = [20, 25, 30, 35];
+        let (canonical, canonical_rates) = robot(standard_ranges()).normalize(&rates).unwrap();
+        assert_eq!(canonical_rates, vec![20, 30, 25, 35]);
+
+        let r = standard_ranges();
+        let shuffled = robot(vec![r[3].clone(), r[0].clone(), r[2].clone(), r[0].clone(), r[1].clone()]);
+        let (normalized, normalized_rates) = shuffled.normalize(&[35, 20, 30, 20, 25]).unwrap();
+        assert_eq!(normalized.time_range, canonical.time_range);
+        assert_eq!(normalized_rates, canonical_rates);
+        assert_eq!(normalized.value(&normalized_rates), robot(standard_ranges()).value(&rates));
+        assert_eq!(shuffled.normalize(&rates).unwrap_err(), RobotRateError::RateCountMismatch { ranges: 5, rates: 4 });
+
+        // the hour from 09:00 is overridden by the range listed after it, which has to stay last
+        let hour = TimeRange::new((NaiveTime::from_hms(9, 0, 0), NaiveTime::from_hms(10, 0, 0)), all_week());
+        let day = TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week());
+        let overridden = RobotWorkTime::new(at("2021-09-10T08:00:00"), at("2021-09-10T12:00:00"), vec![hour.clone(), day.clone()])
+            .with_ramp(1, RateRamp { start_value: 10, end_value: 20 });
+        let (normalized, normalized_rates) = overridden.normalize(&[50, 10]).unwrap();
+        assert_eq!(*normalized.time_range, vec![hour, day]);
+        assert_eq!(normalized.ramps, overridden.ramps);
+        assert_eq!(normalized.value(&normalized_rates), overridden.value(&[50, 10]));
+    }
+
+    #[test]
+    fn intersect_test() {
+        let hm = |h, m| NaiveTime::from_hms(h, m, 0);
+        let range = |s, e| TimeRange::new((s, e), all_week());
+
+        let day = TimeRange::new((hm(7, 0), hm(17, 0)), weekdays());
+        assert_eq!(day.intersect(&range(hm(9, 0), hm(20, 0)).with_priority(1)),
+                   Some(TimeRange::new((hm(9, 0), hm(17, 0)), weekdays()).with_priority(1)));
+
+        let evening = range(hm(18, 0), hm(23, 0));
+        let night = range(hm(22, 0), hm(7, 0));
+        assert_eq!(evening.intersect(&night), Some(range(hm(22, 0), hm(23, 0))));
+        assert_eq!(night.intersect(&range(hm(23, 0), hm(2, 0))), Some(range(hm(23, 0), hm(2, 0))));
+        // in the morning and in the evening, the longer part wins
+        assert_eq!(range(hm(5, 0), hm(23, 0)).intersect(&night), Some(range(hm(5, 0), hm(7, 0))));
+
+        assert_eq!(day.intersect(&evening), None);
+        assert_eq!(day.intersect(&TimeRange::new((hm(7, 0), hm(17, 0)), weekend())), None);
+        // a Friday off for one of the ranges is off for the overlap
+        let holiday = NaiveDate::from_ymd(2021, 12, 24);
+        let overlap = day.with_holidays(&[holiday], false).intersect(&range(hm(8, 0), hm(9, 0))).unwrap();
+        assert!(!overlap.contains(holiday.and_hms(8, 30, 0)));
+        assert!(overlap.contains(holiday.pred().and_hms(8, 30, 0)));
+    }
+
+    #[test]
+    fn covered_hours_test() {
+        let night = TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays());
+        assert_eq!(night.weekday_count(), 5);
+        assert!((night.covered_hours_per_day() - 8.0).abs() < 1e-9);
+        let day = TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(22, 30, 0)), all_week());
+        assert_eq!(day.weekday_count(), 7);
+        assert!((day.covered_hours_per_day() - 15.5).abs() < 1e-9);
+        assert!(day.with_end(day.start).covered_hours_per_day().abs() < 1e-9);
+    }
+
+    #[test]
+    fn contains_matches_next_range_start_test() {
+        // xorshift, so that failures reproduce
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = |n: u64| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed % n
+        };
+        let monday = NaiveDate::from_ymd(2021, 9, 6).and_hms(0, 0, 0);
+        for _ in 0..200 {
+            let time = |quarters: u64| NaiveTime::from_num_seconds_from_midnight(quarters as u32 * 900, 0);
+            let (start, end) = (time(next(96)), time(next(96)));
+            let days = all_week().filter(|_| next(2) == 0).collect();
+            let holiday = (monday + Duration::days(next(14) as i64)).date();
+            let range = TimeRange::from_set((start, end), days).with_holidays(&[holiday], next(2) == 0);
+
+            let random = (0..50).map(|_| monday + Duration::seconds(next(14 * 86400) as i64));
+            let boundaries = (0..14).flat_map(|day| {
+                let d = monday + Duration::days(day);
+                vec![d, d + (start - time(0)), d + (end - time(0))]
+            });
+            for t in random.chain(boundaries).flat_map(|t| vec![t - Duration::seconds(1), t, t + Duration::seconds(1)]) {
+                let starts_at_t = range.get_next_range_start_at(t).map(|(s, _)| s) == Some(t);
+                assert_eq!(range.contains(t), starts_at_t, "{:?} at {}", range, t);
+            }
+        }
+    }
+
+    #[test]
+    fn from_set_test() {
+        let range = (NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0));
+        assert_eq!(TimeRange::from_set(range, weekend().collect()), TimeRange::new(range, weekend()));
+    }
+
+    #[test]
+    fn priority_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T16:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T20:00:00").unwrap(),
+            vec![
+                TimeRange::new((NaiveTime::from_hms(17, 0, 0), NaiveTime::from_hms(19, 0, 0)), all_week()).with_priority(1),
+                TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekdays()),
+                TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays()),
+            ],
+        );
+        let mut it = t.clone().into_iter();
+
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T16:00:00").unwrap(), Some(1))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T17:00:00").unwrap(), Some(0))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T19:00:00").unwrap(), Some(1))));
+        assert_eq!(it.next(), Some((NaiveDateTime::from_str("2021-09-06T20:00:00").unwrap(), None)));
+        assert_eq!(it.next(), None);
+        assert_eq!(t.value(&[50, 20, 25]).unwrap(), 60 * 20 + 120 * 50 + 60 * 20);
+    }
+}
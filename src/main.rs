@@ -1,68 +1,185 @@
 use axum::{handler::get, Router, response::Html};
+use std::convert::Infallible;
 use std::net::SocketAddr;
 
+use std::collections::HashMap;
+use std::fs;
 use std::io::{self, Read};
-use std::str::FromStr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
-use chrono::{Duration, NaiveDateTime, Weekday};
-use serde_json::json;
-
-use robot_rate_calculator::{RobotWorkTime, TimeRange};
-use robot_rate_calculator::schema::RobotWorkSchema;
+use futures::stream::{self, Stream, StreamExt};
+use chrono::NaiveDateTime;
+use lru::LruCache;
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+use robot_rate_calculator::{InstantExplanation, RobotRateError, RobotWorkTime, Schedule};
+use robot_rate_calculator::schema::{CalculationResponse, RoboRate, RobotWorkSchema, Shift, ValueFormat};
+use robot_rate_calculator::validate::{Coverage, Lint};
+use axum::AddExtensionLayer;
+use axum::extract::{Extension, Json, Query};
 use axum::http::StatusCode;
 use axum::handler::post;
+use axum::response::sse::{Event, Sse};
+use axum::routing::BoxRoute;
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
 
-fn weekend() -> impl Iterator<Item=Weekday> {
-    use Weekday::*;
-    vec![Sat, Sun].into_iter()
-}
-
-fn weekday() -> impl Iterator<Item=Weekday> {
-    use Weekday::*;
-    vec![Mon, Tue, Wed, Thu, Fri].into_iter()
-}
-
-fn robot() -> anyhow::Result<()> {
-    let mut buffer = String::new();
-    io::stdin().read_to_string(&mut buffer)?;
-    let work_schema = serde_json::from_str::<RobotWorkSchema>(&buffer)?;
-
-    let start_time = work_schema.shift.start;
-    let end_time = work_schema.shift.end;
-    let time_ranges = vec![
-        TimeRange::new((work_schema.robo_rate.standard_day.start, work_schema.robo_rate.standard_day.end), weekday()),
-        TimeRange::new((work_schema.robo_rate.standard_night.start, work_schema.robo_rate.standard_night.end), weekday()),
-        TimeRange::new((work_schema.robo_rate.extra_day.start, work_schema.robo_rate.extra_day.end), weekend()),
-        TimeRange::new((work_schema.robo_rate.extra_night.start, work_schema.robo_rate.extra_night.end), weekend()),
-    ];
-    let time_range_to_value = vec![
-        work_schema.robo_rate.standard_day.value,
-        work_schema.robo_rate.standard_night.value,
-        work_schema.robo_rate.extra_day.value,
-        work_schema.robo_rate.extra_night.value,
-    ];
-    let n = time_ranges.len();
-
-    let t = RobotWorkTime::new(start_time, end_time, time_ranges);
-    let s = t.clone().into_iter().zip(std::iter::once((NaiveDateTime::from_str("2020-09-19T00:00:00").unwrap(), None)).chain(t.into_iter())).skip(1)
-        .fold(vec![Duration::zero(); n], |mut acc, ((e, _), (s, status))| {
-            if let Some(idx) = status {
-                acc[idx] = acc[idx] + (e - s);
-            }
-            acc
-        });
+#[derive(Parser)]
+#[clap(version, about)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Serves the calculator over HTTP, on the port given by `PORT` (5000 by default)
+    Serve,
+    /// Calculates the schema in FILE, or on stdin if no file is given, as json or in the compact format, e.g.
+    /// `shift=2038-01-01T20:15..2038-01-02T04:15 day=07:00-23:00@20/30 night=23:00-07:00@25/35`
+    Calc { file: Option<PathBuf> },
+}
 
-    let res: u64 = s.iter().zip(time_range_to_value.iter()).map(|(duration, rate)| duration.num_minutes() as u64 * *rate).sum();
+fn robot(file: Option<PathBuf>) -> anyhow::Result<()> {
+    let buffer = match file {
+        Some(file) => fs::read_to_string(file)?,
+        None => {
+            let mut buffer = String::new();
+            io::stdin().read_to_string(&mut buffer)?;
+            buffer
+        }
+    };
+    let work_schema = if buffer.trim_start().starts_with('{') {
+        serde_json::from_str::<RobotWorkSchema>(&buffer)?
+    } else {
+        buffer.trim().parse::<RobotWorkSchema>()?
+    };
+    for warning in work_schema.warnings() {
+        eprintln!("warning: {}", warning);
+    }
 
-    println!("{}", json!({ "value": res }).to_string());
+    let response = work_schema.calculate()?;
+
+    println!("{}", serde_json::to_string(&response)?);
     Ok(())
 }
 
+/// The schedules `/calculate` bills by name.
+type Schedules = Arc<HashMap<String, Schedule>>;
+
+/// Reads the schedules to serve from the json file at `SCHEDULES`, if set, which maps each name to a `roboRate`.
+fn load_schedules() -> anyhow::Result<HashMap<String, Schedule>> {
+    let path = match std::env::var_os("SCHEDULES") {
+        Some(path) => path,
+        None => return Ok(HashMap::new()),
+    };
+    let rates = serde_json::from_str::<HashMap<String, RoboRate>>(&fs::read_to_string(path)?)?;
+    Ok(rates.into_iter().map(|(name, rate)| (name, rate.schedule())).collect())
+}
+
+/// What a response of `/calculate` depends on, other than the schedules the server was started with.
+#[derive(Hash, PartialEq, Eq)]
+enum CacheKey {
+    Named(String, NaiveDateTime, NaiveDateTime),
+    /// The schema, as json.
+    Schema(String),
+}
+
+/// The latest responses of `/calculate`, up to `CACHE_CAPACITY` of them (1024 by default).
+type Cache = Arc<Mutex<LruCache<CacheKey, CalculationResponse>>>;
+
+fn cache(capacity: usize) -> Cache {
+    Arc::new(Mutex::new(LruCache::new(capacity)))
+}
+
+/// Counters of the shifts `/calculate` bills, served by `/metrics` in the Prometheus text format.
+struct Metrics {
+    registry: Registry,
+    calculations: IntCounter,
+    cache_hits: IntCounter,
+    cache_misses: IntCounter,
+    errors: IntCounterVec,
+    duration: Histogram,
+    span_days: Histogram,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let calculations = IntCounter::new("calculations_total", "Calculations billed, those answered from the cache aside").unwrap();
+        let cache_hits = IntCounter::new("calculation_cache_hits_total", "Calculations answered from the cache").unwrap();
+        let cache_misses = IntCounter::new("calculation_cache_misses_total", "Calculations not found in the cache").unwrap();
+        let errors = IntCounterVec::new(Opts::new("calculation_errors_total", "Calculations failed, by kind"), &["kind"]).unwrap();
+        let duration = Histogram::with_opts(HistogramOpts::new("calculation_duration_seconds", "Time taken to bill a shift")).unwrap();
+        let span_days = Histogram::with_opts(
+            HistogramOpts::new("shift_span_days", "Length of the shifts billed, in days")
+                .buckets(vec![0.25, 0.5, 1.0, 2.0, 7.0, 31.0, 365.0]),
+        ).unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(calculations.clone())).unwrap();
+        registry.register(Box::new(cache_hits.clone())).unwrap();
+        registry.register(Box::new(cache_misses.clone())).unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry.register(Box::new(duration.clone())).unwrap();
+        registry.register(Box::new(span_days.clone())).unwrap();
+        Self { registry, calculations, cache_hits, cache_misses, errors, duration, span_days }
+    }
+
+    fn observe_shift(&self, shift: &Shift) {
+        self.calculations.inc();
+        self.span_days.observe((shift.end - shift.start).num_seconds() as f64 / 86400.0);
+    }
+
+    fn error(&self, kind: &str) {
+        self.errors.with_label_values(&[kind]).inc();
+    }
+}
+
+/// The `kind` label of `calculation_errors_total` for `e`.
+fn error_kind(e: &RobotRateError) -> &'static str {
+    match e {
+        RobotRateError::RateCountMismatch { .. } => "rate_count_mismatch",
+        RobotRateError::GroupCountMismatch { .. } => "group_count_mismatch",
+        RobotRateError::DateOutOfRange => "date_out_of_range",
+        RobotRateError::MissingShift => "missing_shift",
+        RobotRateError::MissingRateTable => "missing_rate_table",
+        RobotRateError::InvalidBreakPolicy => "invalid_break_policy",
+        RobotRateError::UncoveredTime { .. } => "uncovered_time",
+        RobotRateError::InvalidGrid { .. } => "invalid_grid",
+        RobotRateError::InvalidCompactSchema { .. } => "invalid_compact_schema",
+        RobotRateError::InvalidTime { .. } => "invalid_time",
+    }
+}
+
+fn app(schedules: Schedules, metrics: Arc<Metrics>, cache: Cache) -> Router<BoxRoute> {
+    Router::new()
+        .route("/", get(root))
+        .route("/square", post(square_handle))
+        .route("/calculate", post(calculate_handle))
+        .route("/calculate/stream", get(calculate_stream_handle))
+        .route("/lint", post(lint_handle))
+        .route("/validate", post(validate_handle))
+        .route("/explain", post(explain_handle))
+        .route("/metrics", get(metrics_handle))
+        .layer(AddExtensionLayer::new(schedules))
+        .layer(AddExtensionLayer::new(metrics))
+        .layer(AddExtensionLayer::new(cache))
+        .boxed()
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let app = Router::new()
-        .route("/", get(root))
-        .route("/square", post(square_handle));
+    match Cli::parse().command {
+        Command::Serve => serve().await,
+        Command::Calc { file } => robot(file),
+    }
+}
+
+async fn serve() -> anyhow::Result<()> {
+    let capacity = std::env::var("CACHE_CAPACITY")
+        .unwrap_or("1024".to_string())
+        .parse::<usize>()?;
+    let app = app(Arc::new(load_schedules()?), Arc::new(Metrics::new()), cache(capacity));
 
     let port = std::env::var("PORT")
         .unwrap_or("5000".to_string())
@@ -85,3 +202,341 @@ async fn square_handle(body: String) -> Result<String, StatusCode> {
     let x = body.parse::<i64>().map_err(|_e| StatusCode::BAD_REQUEST)?;
     x.checked_mul(x).map(|r| r.to_string()).ok_or(StatusCode::BAD_REQUEST)
 }
+
+/// Either a whole schema, or a shift to bill by one of the schedules the server was started with.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CalculateRequest {
+    Named {
+        #[serde(rename = "scheduleName")]
+        schedule_name: String,
+        shift: Shift,
+    },
+    Schema(Box<RobotWorkSchema>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CalculateParams {
+    /// How to write the value, as a number by default.
+    value_format: Option<ValueFormat>,
+}
+
+async fn calculate_handle(
+    Extension(schedules): Extension<Schedules>,
+    Extension(metrics): Extension<Arc<Metrics>>,
+    Extension(cache): Extension<Cache>,
+    Query(params): Query<CalculateParams>,
+    body: String,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // serde does not say which band a bad time is in
+    RobotWorkSchema::validate_times_parse(&body).map_err(|e| {
+        metrics.error(error_kind(&e));
+        (StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+    let request = serde_json::from_str::<CalculateRequest>(&body).map_err(|e| {
+        metrics.error("parse");
+        (StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+    let value_format = params.value_format.unwrap_or(ValueFormat::Number);
+    let key = match &request {
+        CalculateRequest::Named { schedule_name, shift } => CacheKey::Named(schedule_name.clone(), shift.start, shift.end),
+        CalculateRequest::Schema(work_schema) => CacheKey::Schema(serde_json::to_string(work_schema).unwrap()),
+    };
+    let cached = cache.lock().unwrap().get(&key).cloned();
+    if let Some(response) = cached {
+        metrics.cache_hits.inc();
+        return Ok(Json(response.to_json(value_format)));
+    }
+    metrics.cache_misses.inc();
+
+    let timer = metrics.duration.start_timer();
+    let (shift, response) = match request {
+        CalculateRequest::Named { schedule_name, shift } => {
+            let schedule = schedules.get(&schedule_name).ok_or_else(|| {
+                metrics.error("unknown_schedule");
+                (StatusCode::NOT_FOUND, format!("unknown schedule {}", schedule_name))
+            })?;
+            let response = schedule.apply((shift.start, shift.end)).map(|c| CalculationResponse { value: c.value, meta: None });
+            (shift, response)
+        }
+        CalculateRequest::Schema(work_schema) => (work_schema.shift.clone(), work_schema.calculate()),
+    };
+    timer.observe_duration();
+    let response = response.map_err(|e| {
+        metrics.error(error_kind(&e));
+        (StatusCode::BAD_REQUEST, e.to_string())
+    })?;
+    // only shifts actually billed are observed
+    metrics.observe_shift(&shift);
+    let json = response.to_json(value_format);
+    cache.lock().unwrap().put(key, response);
+    Ok(Json(json))
+}
+
+async fn metrics_handle(Extension(metrics): Extension<Arc<Metrics>>) -> String {
+    let mut buffer = vec![];
+    TextEncoder::new().encode(&metrics.registry.gather(), &mut buffer).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+async fn lint_handle(Json(work_schema): Json<RobotWorkSchema>) -> Json<Vec<Lint>> {
+    Json(work_schema.lint())
+}
+
+async fn validate_handle(Json(work_schema): Json<RobotWorkSchema>) -> Json<Coverage> {
+    Json(work_schema.coverage())
+}
+
+#[derive(Deserialize)]
+struct ExplainRequest {
+    schema: RobotWorkSchema,
+    instant: NaiveDateTime,
+}
+
+#[derive(Serialize)]
+struct ExplainResponse {
+    #[serde(flatten)]
+    explanation: InstantExplanation,
+    /// The name of the band applying at the instant.
+    band: Option<&'static str>,
+}
+
+async fn explain_handle(Json(request): Json<ExplainRequest>) -> Json<ExplainResponse> {
+    let (robot, _) = RobotWorkTime::from_schema(&request.schema);
+    let explanation = robot.explain_instant(request.instant);
+    let band = explanation.range.as_ref().map(|range| request.schema.robo_rate.bands()[range.idx].0);
+    Json(ExplainResponse { explanation, band })
+}
+
+#[derive(Deserialize)]
+struct StreamParams {
+    schema: String,
+}
+
+/// Streams one event per segment as the shift is walked, followed by a `total` event carrying the response.
+async fn calculate_stream_handle(Query(params): Query<StreamParams>) -> Result<Sse<impl Stream<Item=Result<Event, Infallible>>>, StatusCode> {
+    let work_schema = serde_json::from_str::<RobotWorkSchema>(&params.schema).map_err(|_e| StatusCode::BAD_REQUEST)?;
+    let (robot, _) = RobotWorkTime::from_schema(&work_schema);
+    // an uncovered shift start fails up front rather than midway through the stream
+    let segments = robot.into_segments().map_err(|_e| StatusCode::BAD_REQUEST)?
+        .map(|segment| Event::default().data(serde_json::to_string(&segment).unwrap()));
+    let total = std::iter::once_with(move || match work_schema.calculate() {
+        Ok(response) => Event::default().event("total").data(serde_json::to_string(&response).unwrap()),
+        Err(e) => Event::default().event("error").data(e.to_string()),
+    });
+    Ok(Sse::new(stream::iter(segments.chain(total)).map(Ok)))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn percent_encode(s: &str) -> String {
+        s.bytes().map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        }).collect()
+    }
+
+    #[tokio::test]
+    async fn calculate_stream_test() {
+        let schema = r#"{
+  "shift": { "start": "2038-01-01T22:00:00", "end": "2038-01-01T23:30:00" },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  }
+}"#;
+        let uri = format!("/calculate/stream?schema={}", percent_encode(schema));
+        let response = app(Schedules::default(), Arc::new(Metrics::new()), cache(16)).oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let events = body.split("\n\n").filter(|e| !e.is_empty()).collect::<Vec<_>>();
+        assert_eq!(events.len(), 3);
+        assert!(events[0].contains(r#"{"start":"2038-01-01T22:00:00","end":"2038-01-01T23:00:00","status":0,"kind":"work"}"#));
+        assert!(events[1].contains(r#"{"start":"2038-01-01T23:00:00","end":"2038-01-01T23:30:00","status":1,"kind":"work"}"#));
+        assert!(events[2].contains("total"));
+        assert!(events[2].contains(r#"{"value":1950}"#));
+
+        let uncovered = schema.replace(r#""start": "07:00:00", "end": "23:00:00", "value": 20"#, r#""start": "07:00:00", "end": "21:00:00", "value": 20"#);
+        let uri = format!("/calculate/stream?schema={}", percent_encode(&uncovered));
+        let response = app(Schedules::default(), Arc::new(Metrics::new()), cache(16)).oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn metrics_test() {
+        let metrics = Arc::new(Metrics::new());
+        let cache = cache(16);
+        let sample = include_str!("../sample_input.json");
+        let unknown = r#"{"scheduleName": "unknown", "shift": { "start": "2038-01-01T20:15:00", "end": "2038-01-02T04:15:00" }}"#;
+        let requests = [(sample, StatusCode::OK), (sample, StatusCode::OK), (unknown, StatusCode::NOT_FOUND), ("{", StatusCode::BAD_REQUEST)];
+        for (schema, status) in requests.iter() {
+            let request = Request::builder()
+                .method("POST")
+                .uri("/calculate")
+                .header("content-type", "application/json")
+                .body(Body::from(*schema))
+                .unwrap();
+            let response = app(Schedules::default(), metrics.clone(), cache.clone()).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), *status);
+        }
+
+        let request = Request::builder().uri("/metrics").body(Body::empty()).unwrap();
+        let response = app(Schedules::default(), metrics, cache).oneshot(request).await.unwrap();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        // the repeated sample is answered from the cache, and not observed again, nor is the unknown schedule
+        assert!(body.contains("calculations_total 1\n"));
+        assert!(body.contains("calculation_cache_hits_total 1\n"));
+        assert!(body.contains("calculation_errors_total{kind=\"unknown_schedule\"} 1\n"));
+        assert!(body.contains("calculation_errors_total{kind=\"parse\"} 1\n"));
+        assert!(body.contains("shift_span_days_bucket{le=\"0.25\"} 0\n"));
+        assert!(body.contains("shift_span_days_bucket{le=\"0.5\"} 1\n"));
+        assert!(body.contains("shift_span_days_count 1\n"));
+    }
+
+    #[tokio::test]
+    async fn validate_test() {
+        let schema = r#"{
+  "shift": { "start": "2038-01-01T22:00:00", "end": "2038-01-01T23:30:00" },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "22:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "22:00:00", "end": "07:00:00", "value": 35 }
+  }
+}"#;
+        let request = Request::builder()
+            .method("POST")
+            .uri("/validate")
+            .header("content-type", "application/json")
+            .body(Body::from(schema))
+            .unwrap();
+        let response = app(Schedules::default(), Arc::new(Metrics::new()), cache(16)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let coverage = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        assert_eq!(coverage, serde_json::json!({
+            "gaps": [{ "weekdays": ["Mon", "Tue", "Wed", "Thu", "Fri"], "start": "22:00:00", "end": "23:00:00", "bands": [] }],
+            "overlaps": [{ "weekdays": ["Sat", "Sun"], "start": "22:00:00", "end": "23:00:00", "bands": ["extraDay", "extraNight"] }],
+        }));
+    }
+
+    #[tokio::test]
+    async fn named_schedule_test() {
+        let standard = serde_json::from_str::<RobotWorkSchema>(include_str!("../sample_input.json")).unwrap().robo_rate;
+        let mut premium = standard.clone();
+        for band in [&mut premium.standard_day, &mut premium.standard_night, &mut premium.extra_day, &mut premium.extra_night].iter_mut() {
+            band.value *= 2;
+        }
+        let schedules = vec![("standard", standard), ("premium", premium)].into_iter()
+            .map(|(name, rate)| (name.to_string(), rate.schedule()))
+            .collect::<HashMap<_, _>>();
+        let schedules = Arc::new(schedules);
+
+        for (name, status, value) in [("standard", StatusCode::OK, Some(13725)), ("premium", StatusCode::OK, Some(27450)), ("unknown", StatusCode::NOT_FOUND, None)].iter() {
+            let body = format!(
+                r#"{{"scheduleName": "{}", "shift": {{ "start": "2038-01-01T20:15:00", "end": "2038-01-02T04:15:00" }}}}"#,
+                name,
+            );
+            let request = Request::builder()
+                .method("POST")
+                .uri("/calculate")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let response = app(schedules.clone(), Arc::new(Metrics::new()), cache(16)).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), *status);
+            if let Some(value) = value {
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap(), serde_json::json!({ "value": value }));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn cache_test() {
+        let standard = serde_json::from_str::<RobotWorkSchema>(include_str!("../sample_input.json")).unwrap().robo_rate;
+        let schedules = Arc::new(vec![("standard".to_string(), standard.schedule())].into_iter().collect::<HashMap<_, _>>());
+        let (metrics, cache) = (Arc::new(Metrics::new()), cache(2));
+
+        // the first day is evicted by the third, and recomputed
+        for (day, hits, misses) in [(1, 0, 1), (2, 0, 2), (1, 1, 2), (3, 1, 3), (2, 1, 4), (1, 1, 5), (1, 2, 5)].iter() {
+            let body = format!(
+                r#"{{"scheduleName": "standard", "shift": {{ "start": "2038-01-0{}T20:15:00", "end": "2038-01-0{}T04:15:00" }}}}"#,
+                day, day + 1,
+            );
+            let request = Request::builder()
+                .method("POST")
+                .uri("/calculate")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            let response = app(schedules.clone(), metrics.clone(), cache.clone()).oneshot(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!((metrics.cache_hits.get(), metrics.cache_misses.get()), (*hits, *misses));
+        }
+        assert_eq!(cache.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn invalid_time_test() {
+        let schema = include_str!("../sample_input.json").replacen("\"23:00:00\"", "\"25:00:00\"", 1);
+        let request = Request::builder()
+            .method("POST")
+            .uri("/calculate")
+            .header("content-type", "application/json")
+            .body(Body::from(schema))
+            .unwrap();
+        let response = app(Schedules::default(), Arc::new(Metrics::new()), cache(16)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"roboRate.standardDay.end is not a valid time: '25:00:00'");
+    }
+
+    #[tokio::test]
+    async fn explain_test() {
+        let request = r#"{
+  "schema": {
+    "shift": { "start": "2038-01-01T20:15:00", "end": "2038-01-02T04:15:00" },
+    "roboRate": {
+      "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+      "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+      "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+      "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+    }
+  },
+  "instant": "2038-01-02T02:30:00"
+}"#;
+        let request = Request::builder()
+            .method("POST")
+            .uri("/explain")
+            .header("content-type", "application/json")
+            .body(Body::from(request))
+            .unwrap();
+        let response = app(Schedules::default(), Arc::new(Metrics::new()), cache(16)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let explanation = serde_json::from_slice::<serde_json::Value>(&body).unwrap();
+        assert_eq!(explanation, serde_json::json!({
+            "weekday": "Sat",
+            "tier": 3,
+            "range": { "idx": 3, "start": "23:00:00", "end": "07:00:00", "weekdays": ["Sat", "Sun"], "holiday": false },
+            "excluded": [],
+            "kind": "work",
+            "band": "extraNight",
+        }));
+    }
+}
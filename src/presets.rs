@@ -0,0 +1,40 @@
+use chrono::Weekday;
+
+/// Monday to Friday.
+pub fn weekdays() -> impl Iterator<Item=Weekday> {
+    use Weekday::*;
+    vec![Mon, Tue, Wed, Thu, Fri].into_iter()
+}
+
+/// Saturday and Sunday.
+pub fn weekend() -> impl Iterator<Item=Weekday> {
+    use Weekday::*;
+    vec![Sat, Sun].into_iter()
+}
+
+/// Every day of the week.
+pub fn all_week() -> impl Iterator<Item=Weekday> {
+    weekdays().chain(weekend())
+}
+
+/// A single day of the week.
+pub fn single(day: Weekday) -> impl Iterator<Item=Weekday> {
+    std::iter::once(day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn all_week_test() {
+        let days = all_week().collect::<HashSet<_>>();
+        assert_eq!(days.len(), 7);
+        for day in weekdays().chain(weekend()) {
+            assert!(days.contains(&day));
+        }
+        assert_eq!(single(Weekday::Wed).collect::<Vec<_>>(), vec![Weekday::Wed]);
+    }
+}
@@ -0,0 +1,50 @@
+// The types of `calculation.proto`, written to match it, as the build does not run protoc. `wire_format_test` pins
+// the tags and types down.
+
+/// A billed shift, see `Calculation`. Datetimes are naive, in seconds since 1970-01-01T00:00:00.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Calculation {
+    #[prost(uint64, tag="1")]
+    pub value: u64,
+    /// Time worked at each time range, in seconds.
+    #[prost(int64, repeated, tag="2")]
+    pub breakdown: ::prost::alloc::vec::Vec<i64>,
+    #[prost(uint64, repeated, tag="3")]
+    pub values: ::prost::alloc::vec::Vec<u64>,
+    #[prost(message, repeated, tag="4")]
+    pub segments: ::prost::alloc::vec::Vec<Segment>,
+    /// Seconds from midnight.
+    #[prost(uint32, tag="5")]
+    pub day_start: u32,
+    #[prost(enumeration="MinuteRounding", tag="6")]
+    pub minute_rounding: i32,
+    #[prost(uint64, tag="7")]
+    pub base_fee: u64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Segment {
+    #[prost(int64, tag="1")]
+    pub start: i64,
+    #[prost(int64, tag="2")]
+    pub end: i64,
+    /// The time range billed, unset when the robot is not working.
+    #[prost(uint64, optional, tag="3")]
+    pub status: ::core::option::Option<u64>,
+    #[prost(enumeration="SegmentKind", tag="4")]
+    pub kind: i32,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SegmentKind {
+    Work = 0,
+    Break = 1,
+    Maintenance = 2,
+    OffDuty = 3,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum MinuteRounding {
+    Floor = 0,
+    Round = 1,
+    Ceil = 2,
+}
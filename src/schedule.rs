@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::{Duration, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::{BreakMode, BreakPolicy, Calculation, RateUnit, RobotRateError, RobotWorkTime, TimeRange};
+use crate::builder::first_uncovered;
+use crate::schema::RateRamp;
+
+/// How a shift is billed beyond the rates of its time ranges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BillingPolicy {
+    pub break_mode: BreakMode,
+    /// See `RobotWorkTime::with_max_daily_value`.
+    pub max_daily_value: Option<u64>,
+    /// See `RobotWorkTime::with_streak_multipliers`.
+    pub streak_multipliers: Vec<(Duration, f64)>,
+    /// See `RobotWorkTime::with_rate_unit`.
+    pub rate_unit: RateUnit,
+    /// See `RobotWorkTime::with_base_fee`.
+    pub base_fee: u64,
+}
+
+impl Default for BillingPolicy {
+    fn default() -> Self {
+        Self {
+            break_mode: BreakMode::Unbilled,
+            max_daily_value: None,
+            streak_multipliers: vec![],
+            rate_unit: RateUnit::PerMinute,
+            base_fee: 0,
+        }
+    }
+}
+
+/// The time ranges with their rates, the break policy and the billing policy of a robot, to bill any of its
+/// shifts with. The robots of its shifts share its time ranges, and it can be shared across threads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    time_range: Arc<Vec<TimeRange>>,
+    /// The first time of the week no range covers, found once for all shifts.
+    uncovered: Option<(Weekday, NaiveTime)>,
+    rates: Vec<u64>,
+    ramps: BTreeMap<usize, RateRamp>,
+    break_policy: Option<(Duration, BreakPolicy)>,
+    billing_policy: BillingPolicy,
+}
+
+impl Schedule {
+    /// Bills each of `time_range` at the per-minute rate of the same index in `rates`.
+    pub fn new(time_range: Vec<TimeRange>, rates: Vec<u64>) -> Self {
+        Self {
+            uncovered: first_uncovered(&time_range),
+            time_range: Arc::new(time_range),
+            rates,
+            ramps: BTreeMap::new(),
+            break_policy: None,
+            billing_policy: BillingPolicy::default(),
+        }
+    }
+
+    /// See `RobotWorkTimeBuilder::break_policy`.
+    pub fn with_break_policy(mut self, work: Duration, rest: impl Into<BreakPolicy>) -> Self {
+        self.break_policy = Some((work, rest.into()));
+        self
+    }
+
+    /// See `RobotWorkTime::with_ramp`.
+    pub fn with_ramp(mut self, idx: usize, ramp: RateRamp) -> Self {
+        self.ramps.insert(idx, ramp);
+        self
+    }
+
+    /// Bills the robots with `billing_policy`, see `BillingPolicy` for the options it sets.
+    pub fn with_billing_policy(mut self, billing_policy: BillingPolicy) -> Self {
+        self.billing_policy = billing_policy;
+        self
+    }
+
+    /// The robot working `shift` on this schedule, validated as by `RobotWorkTimeBuilder::build`.
+    pub fn robot(&self, shift: (NaiveDateTime, NaiveDateTime)) -> Result<RobotWorkTime, RobotRateError> {
+        if let Some((weekday, time)) = self.uncovered {
+            return Err(RobotRateError::UncoveredTime { weekday, time });
+        }
+        let mut robot = RobotWorkTime::new(shift.0, shift.1, self.time_range.clone())
+            .with_break_mode(self.billing_policy.break_mode)
+            .with_streak_multipliers(self.billing_policy.streak_multipliers.clone())
+            .with_rate_unit(self.billing_policy.rate_unit)
+            .with_base_fee(self.billing_policy.base_fee);
+        if let Some((work, rest)) = self.break_policy {
+            robot = robot.rebreak(work, rest)?;
+        }
+        if let Some(cap) = self.billing_policy.max_daily_value {
+            robot = robot.with_max_daily_value(cap);
+        }
+        for (idx, ramp) in self.ramps.iter() {
+            robot = robot.with_ramp(*idx, *ramp);
+        }
+        Ok(robot)
+    }
+
+    /// Bills `shift` on this schedule.
+    pub fn apply(&self, shift: (NaiveDateTime, NaiveDateTime)) -> Result<Calculation, RobotRateError> {
+        self.robot(shift)?.calculate(&self.rates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::standard_ranges;
+
+    #[test]
+    fn apply_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let schedule = Schedule::new(standard_ranges(), vec![20, 25, 30, 35])
+            .with_break_policy(Duration::hours(4), Duration::minutes(30))
+            .with_billing_policy(BillingPolicy { break_mode: BreakMode::FractionOfInterruptedTier(0.5), ..BillingPolicy::default() });
+
+        let c = schedule.apply((at("2021-09-06T07:00:00"), at("2021-09-06T12:00:00"))).unwrap();
+        assert_eq!(c.value, 270 * 20 + 30 * 20 / 2);
+        // a Saturday night, with the break at 03:00
+        let c = schedule.apply((at("2021-09-11T23:00:00"), at("2021-09-12T05:00:00"))).unwrap();
+        assert_eq!(c.value, 330 * 35 + 30 * 35 / 2);
+
+        let partial = Schedule::new(vec![standard_ranges().remove(0)], vec![20]);
+        assert!(matches!(partial.apply((at("2021-09-06T07:00:00"), at("2021-09-06T12:00:00"))), Err(RobotRateError::UncoveredTime { .. })));
+    }
+
+    #[test]
+    fn shared_test() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Schedule>();
+        assert_send_sync::<RobotWorkTime>();
+
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let schedule = Arc::new(Schedule::new(standard_ranges(), vec![20, 25, 30, 35]).with_break_policy(Duration::hours(4), Duration::minutes(30)));
+        let shifts = (0..8).map(|day| {
+            let start = at("2021-09-06T07:00:00") + Duration::days(day) + Duration::minutes(day * 7);
+            (start, start + Duration::hours(10))
+        }).collect::<Vec<_>>();
+
+        let mut threads = Vec::new();
+        for &shift in &shifts {
+            let schedule = schedule.clone();
+            threads.push(std::thread::spawn(move || schedule.apply(shift).unwrap()));
+        }
+        for (thread, shift) in threads.into_iter().zip(shifts) {
+            assert_eq!(thread.join().unwrap(), schedule.apply(shift).unwrap());
+        }
+        // the robots share the time ranges of the schedule
+        assert!(Arc::ptr_eq(&schedule.robot((at("2021-09-06T07:00:00"), at("2021-09-06T08:00:00"))).unwrap().time_range, &schedule.time_range));
+    }
+}
@@ -1,63 +1,804 @@
+use std::str::FromStr;
+use std::sync::Arc;
+
 use serde::{Serialize, Deserialize};
-use chrono::{NaiveDateTime, NaiveTime};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+use crate::{ranges_from_parts, BillingPolicy, RateUnit, RobotRateError, RobotWorkTime, Schedule, TimeRange};
+use crate::presets::{all_week, weekdays, weekend};
 
+/// Read through `band::RawSchema`, rejecting schemas whose band values, those of the rate changes included, are
+/// not all numbers or all decimal strings.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", try_from = "band::RawSchema")]
 pub struct RobotWorkSchema {
     pub shift: Shift,
     pub robo_rate: RoboRate,
+    /// Passed through untouched, and echoed back with the calculation.
+    pub meta: Option<Meta>,
+    pub rate_changes: Vec<RateChange>,
 }
 
+/// New band values, billed from `effective` on. The bands keep the windows of the schema's `roboRate`.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Shift {
-    pub start: NaiveDateTime,
-    pub end: NaiveDateTime,
+pub struct RateChange {
+    #[serde(with = "compact::datetime")]
+    pub effective: NaiveDateTime,
+    pub robo_rate: RoboRate,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct RoboRate {
-    pub standard_day: StandardDay,
-    pub standard_night: StandardNight,
-    pub extra_day: ExtraDay,
-    pub extra_night: ExtraNight,
+pub struct Meta {
+    #[serde(default)]
+    pub robot_id: Option<String>,
+    #[serde(default)]
+    pub cost_center: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct StandardDay {
-    pub start: NaiveTime,
-    pub end: NaiveTime,
+pub struct CalculationResponse {
+    #[serde(deserialize_with = "lossless::integer")]
     pub value: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<Meta>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// How the value of a `CalculationResponse` is written as JSON.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct StandardNight {
-    pub start: NaiveTime,
-    pub end: NaiveTime,
-    pub value: u64,
+pub enum ValueFormat {
+    /// A number, which clients parsing numbers as doubles, such as JavaScript, round above 2^53.
+    Number,
+    /// A string of digits, kept exact by any client.
+    String,
+}
+
+impl CalculationResponse {
+    /// The response as JSON, with the value written as `format`. Either format is read back.
+    pub fn to_json(&self, format: ValueFormat) -> serde_json::Value {
+        let mut json = serde_json::to_value(self).expect("a response is always valid JSON");
+        if format == ValueFormat::String {
+            json["value"] = self.value.to_string().into();
+        }
+        json
+    }
+}
+
+impl Default for RobotWorkSchema {
+    /// The default rates over a placeholder shift, from 20:15 on Friday 1 January 2038 to 04:15 the next day.
+    fn default() -> Self {
+        Self {
+            shift: Shift {
+                start: NaiveDate::from_ymd(2038, 1, 1).and_hms(20, 15, 0),
+                end: NaiveDate::from_ymd(2038, 1, 2).and_hms(4, 15, 0),
+            },
+            robo_rate: RoboRate::default(),
+            meta: None,
+            rate_changes: vec![],
+        }
+    }
+}
+
+/// Reads the compact one-line format, e.g.
+/// `shift=2038-01-01T20:15..2038-01-02T04:15 day=07:00-23:00@20/30 night=23:00-07:00@25/35`: the shift, and the
+/// day and night windows with their standard and extra values. Seconds may be given as well.
+impl FromStr for RobotWorkSchema {
+    type Err = RobotRateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |part: &str| RobotRateError::InvalidCompactSchema { part: part.to_string() };
+        let time = |s: &str| NaiveTime::parse_from_str(s, "%H:%M:%S").or_else(|_| NaiveTime::parse_from_str(s, "%H:%M")).ok();
+        let datetime = |s: &str| {
+            NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")).ok()
+        };
+        // `07:00-23:00@20/30` into the standard and the extra band
+        let bands = |s: &str| -> Option<(RateBand, RateBand)> {
+            let (window, values) = s.split_once('@')?;
+            let (start, end) = window.split_once('-')?;
+            let (standard, extra) = values.split_once('/')?;
+            let band = |value: &str, weekdays: Vec<Weekday>| Some(RateBand {
+                start: time(start)?,
+                end: time(end)?,
+                value: value.parse().ok()?,
+                ramp: None,
+                priority: 0,
+                weekdays: Some(weekdays),
+            });
+            Some((band(standard, weekdays().collect())?, band(extra, weekend().collect())?))
+        };
+
+        let (mut shift, mut day, mut night) = (None, None, None);
+        for part in s.split_whitespace() {
+            let (key, value) = part.split_once('=').ok_or_else(|| invalid(part))?;
+            let parsed = match key {
+                "shift" if shift.is_none() => value.split_once("..")
+                    .and_then(|(start, end)| Some(Shift { start: datetime(start)?, end: datetime(end)? }))
+                    .map(|parsed| shift = Some(parsed)),
+                "day" if day.is_none() => bands(value).map(|parsed| day = Some(parsed)),
+                "night" if night.is_none() => bands(value).map(|parsed| night = Some(parsed)),
+                _ => None,
+            };
+            parsed.ok_or_else(|| invalid(part))?;
+        }
+        let (shift, (standard_day, extra_day), (standard_night, extra_night)) = (
+            shift.ok_or_else(|| invalid("shift"))?,
+            day.ok_or_else(|| invalid("day"))?,
+            night.ok_or_else(|| invalid("night"))?,
+        );
+        Ok(RobotWorkSchema {
+            shift,
+            robo_rate: RoboRate { standard_day, standard_night, extra_day, extra_night, ..RoboRate::default() },
+            ..RobotWorkSchema::default()
+        })
+    }
+}
+
+impl RobotWorkSchema {
+    pub fn calculate(&self) -> Result<CalculationResponse, RobotRateError> {
+        let (t, rates) = RobotWorkTime::from_schema(self);
+        Ok(CalculationResponse {
+            value: t.value(&rates)?,
+            meta: self.meta.clone(),
+        })
+    }
+
+    /// The value of the shift for each of `alternatives`, the weekdays billed at the standard bands, the others
+    /// being billed at the extra bands, e.g. to weigh billing Saturdays as standard days. The shift is walked once,
+    /// and the breaks stay where this schema places them.
+    pub fn compare_weekdays(&self, alternatives: &[Vec<Weekday>]) -> Result<Vec<u64>, RobotRateError> {
+        let (robot, _) = RobotWorkTime::from_schema(self);
+        robot.check_representable()?;
+        let segments = robot.segments()?;
+        alternatives.iter()
+            .map(|standard| {
+                let extra = all_week().filter(|d| !standard.contains(d)).collect::<Vec<_>>();
+                // rate changes keep the windows of `robo_rate`
+                let mut schema = self.clone();
+                let rate = &mut schema.robo_rate;
+                rate.standard_day.weekdays = Some(standard.clone());
+                rate.standard_night.weekdays = Some(standard.clone());
+                rate.extra_day.weekdays = Some(extra.clone());
+                rate.extra_night.weekdays = Some(extra);
+                let (alternative, rates) = RobotWorkTime::from_schema(&schema);
+                Ok(alternative.bill(&rates, alternative.reclassify(&segments))?.value)
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ExtraDay {
-    pub start: NaiveTime,
-    pub end: NaiveTime,
-    pub value: u64,
+pub struct Shift {
+    #[serde(with = "compact::datetime")]
+    pub start: NaiveDateTime,
+    #[serde(with = "compact::datetime")]
+    pub end: NaiveDateTime,
+}
+
+/// Read through `band::RawRoboRate`, which fills in the default weekdays of the bands and rejects a mix of number
+/// and decimal string values.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", try_from = "band::RawRoboRate")]
+pub struct RoboRate {
+    pub standard_day: RateBand,
+    pub standard_night: RateBand,
+    pub extra_day: RateBand,
+    pub extra_night: RateBand,
+    /// Band names by precedence, highest first, deciding between overlapping bands of the same `priority` instead
+    /// of the band listed last. Bands left out come after, in their usual order. A schema naming a band that does
+    /// not exist, or naming one twice, is rejected.
+    pub precedence: Vec<String>,
+    /// What the band values, and those of the rate changes, are quoted per, `perMinute` by default.
+    pub rate_unit: RateUnit,
+    /// A flat fee added to each non-empty shift, not quoted per `rateUnit`. That of a rate change is ignored. Always
+    /// a plain integer, in the unit the value of the shift comes out in: in hundredths when the band values are
+    /// decimal strings, so `500` is then a fee of 5.00.
+    pub base_fee: u64,
+}
+
+impl Default for RoboRate {
+    /// The sample rates: 20 by day and 25 by night on weekdays, 30 and 35 on the weekend, days running from 07:00
+    /// to 23:00.
+    fn default() -> Self {
+        let band = |start, end, value, weekdays: Vec<Weekday>| RateBand {
+            start: NaiveTime::from_hms(start, 0, 0),
+            end: NaiveTime::from_hms(end, 0, 0),
+            value,
+            ramp: None,
+            priority: 0,
+            weekdays: Some(weekdays),
+        };
+        Self {
+            standard_day: band(7, 23, 20, weekdays().collect()),
+            standard_night: band(23, 7, 25, weekdays().collect()),
+            extra_day: band(7, 23, 30, weekend().collect()),
+            extra_night: band(23, 7, 35, weekend().collect()),
+            precedence: vec![],
+            rate_unit: RateUnit::PerMinute,
+            base_fee: 0,
+        }
+    }
+}
+
+impl RoboRate {
+    /// The index of the band applying at `datetime`, see `RateBand::applies_at`. Among overlapping bands, the one
+    /// with the highest priority wins, then by `precedence`, and then the last one, as for the time ranges of
+    /// `from_schema`.
+    pub fn band_at(&self, datetime: NaiveDateTime) -> Option<usize> {
+        self.bands().iter().enumerate()
+            .filter(|(_, (_, band))| band.applies_at(datetime))
+            .max_by_key(|(idx, _)| (self.priority(*idx), *idx))
+            .map(|(idx, _)| idx)
+    }
+
+    /// The priority of the time range of the band at `idx`, folding `precedence` into the band priority. Only the
+    /// first mention of each band counts, so the rank never reaches the next priority, which is a step of 8 apart
+    /// for band priorities within `RateBand::MAX_PRIORITY`.
+    fn priority(&self, idx: usize) -> i32 {
+        let bands = self.bands();
+        let (name, band) = bands[idx];
+        if self.precedence.is_empty() {
+            return band.priority;
+        }
+        let mut ranked: Vec<&str> = Vec::with_capacity(bands.len());
+        for n in self.precedence.iter() {
+            if bands.iter().any(|(known, _)| known == n) && !ranked.contains(&n.as_str()) {
+                ranked.push(n.as_str());
+            }
+        }
+        let rank = ranked.iter()
+            .position(|n| *n == name)
+            .map_or(0, |pos| ranked.len() - pos);
+        band.priority.saturating_mul(8).saturating_add(rank as i32)
+    }
+
+    /// Reads the time ranges of a weekly grid, each paired with its tier id. The grid is a CSV whose header names
+    /// the weekday of each column after the first, e.g. `time,Mon,Tue,Wed,Thu,Fri,Sat,Sun`, followed by one row per
+    /// half-hour slot of the day in order, from `00:00` to `23:30`, each starting with the slot time. A cell holds
+    /// the tier id of its slot, or nothing if the slot is not covered.
+    ///
+    /// Adjacent slots of the same tier merge into one range, and so do the slots before 07:00 and from 23:00, say,
+    /// into an overnight range. Weekdays with the same range of a tier share a `TimeRange`.
+    pub fn from_grid_csv(csv: &str) -> Result<Vec<(usize, TimeRange)>, RobotRateError> {
+        let mut lines = csv.lines().enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let (header_line, header) = lines.next().ok_or(RobotRateError::InvalidGrid { line: 1 })?;
+        let weekdays = header.split(',').skip(1)
+            .map(|day| day.trim().parse::<Weekday>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| RobotRateError::InvalidGrid { line: header_line })?;
+
+        const SLOTS: usize = 48;
+        let mut columns = vec![Vec::with_capacity(SLOTS); weekdays.len()];
+        let mut last_line = header_line;
+        for (slot, (line, row)) in lines.enumerate() {
+            let invalid = RobotRateError::InvalidGrid { line };
+            let mut cells = row.split(',').map(str::trim);
+            let time = cells.next().and_then(|time| NaiveTime::parse_from_str(time, "%H:%M").ok());
+            if slot >= SLOTS || time != Some(NaiveTime::from_hms(0, 0, 0) + Duration::minutes(30 * slot as i64)) {
+                return Err(invalid);
+            }
+            let tiers = cells
+                .map(|cell| if cell.is_empty() { Ok(None) } else { cell.parse::<usize>().map(Some) })
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid.clone())?;
+            if tiers.len() != weekdays.len() {
+                return Err(invalid);
+            }
+            for (column, tier) in columns.iter_mut().zip(tiers) {
+                column.push(tier);
+            }
+            last_line = line;
+        }
+        if columns.iter().any(|column| column.len() != SLOTS) {
+            return Err(RobotRateError::InvalidGrid { line: last_line + 1 });
+        }
+
+        let slot_time = |slot: usize| match slot {
+            SLOTS => None,
+            _ => Some(NaiveTime::from_hms(0, 0, 0) + Duration::minutes(30 * slot as i64)),
+        };
+        let mut parts = vec![];
+        for (weekday, column) in weekdays.iter().zip(columns) {
+            let mut start = 0;
+            for slot in 1..=SLOTS {
+                if slot == SLOTS || column[slot] != column[start] {
+                    if let Some(tier) = column[start] {
+                        parts.push((tier, *weekday, slot_time(start).unwrap(), slot_time(slot)));
+                    }
+                    start = slot;
+                }
+            }
+        }
+        Ok(ranges_from_parts(parts))
+    }
+
+    /// The schedule billing any shift at these bands, with the time ranges of `RobotWorkTime::from_schema`.
+    pub fn schedule(&self) -> Schedule {
+        let mut schedule = Schedule::new(self.time_ranges(), self.values())
+            .with_billing_policy(BillingPolicy { rate_unit: self.rate_unit, base_fee: self.base_fee, ..BillingPolicy::default() });
+        for (idx, (_, band)) in self.bands().iter().enumerate() {
+            if let Some(ramp) = band.ramp {
+                schedule = schedule.with_ramp(idx, ramp);
+            }
+        }
+        schedule
+    }
+
+    /// A time range for each band, applying on the days of the band.
+    fn time_ranges(&self) -> Vec<TimeRange> {
+        self.bands().iter().enumerate()
+            .map(|(idx, (_, band))| band.to_time_range().with_priority(self.priority(idx)))
+            .collect()
+    }
+
+    /// The per-minute rate of each band.
+    fn values(&self) -> Vec<u64> {
+        self.bands().iter().map(|(_, band)| band.value).collect()
+    }
+
+    /// The names of the bands which apply on holidays, see `RobotWorkTime::from_schema_with_holidays`.
+    pub const HOLIDAY_BANDS: [&str; 2] = ["extraDay", "extraNight"];
+
+    /// The bands paired with their JSON names, in time range order.
+    pub fn bands(&self) -> [(&'static str, &RateBand); 4] {
+        [
+            ("standardDay", &self.standard_day),
+            ("standardNight", &self.standard_night),
+            ("extraDay", &self.extra_day),
+            ("extraNight", &self.extra_night),
+        ]
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct ExtraNight {
+pub struct RateBand {
+    #[serde(with = "compact::time")]
     pub start: NaiveTime,
+    #[serde(with = "compact::time")]
     pub end: NaiveTime,
+    /// Per-minute rate. A string such as `"20.50"` is read as a decimal with up to two places, in hundredths,
+    /// e.g. cents, which the value of the shift then comes out in as well. A schema mixing strings and numbers
+    /// across its bands, or those of its rate changes, is rejected, as the numbers would be billed as hundredths.
+    #[serde(deserialize_with = "decimal::hundredths")]
     pub value: u64,
+    #[serde(default)]
+    pub ramp: Option<RateRamp>,
+    /// Where bands overlap, the one with the highest priority applies. Ties go to the band listed last. A schema
+    /// with a priority beyond `MAX_PRIORITY` either way is rejected.
+    #[serde(default)]
+    pub priority: i32,
+    /// Days the band applies on. When reading a schema, standard bands default to weekdays and extra bands to the
+    /// weekend. A band without any applies every day.
+    #[serde(default, with = "compact::weekdays")]
+    pub weekdays: Option<Vec<Weekday>>,
+}
+
+impl RateBand {
+    /// The largest priority, in magnitude, that leaves room to fold `RoboRate::precedence` in, see
+    /// `RoboRate::band_at`.
+    pub const MAX_PRIORITY: i32 = i32::MAX / 8;
+
+    /// Whether the band covers `datetime`, by the same rules as `TimeRange::contains`.
+    pub fn applies_at(&self, datetime: NaiveDateTime) -> bool {
+        self.to_time_range().contains(datetime)
+    }
+
+    fn to_time_range(&self) -> TimeRange {
+        let weekdays = match &self.weekdays {
+            Some(weekdays) => weekdays.clone(),
+            None => all_week().collect(),
+        };
+        TimeRange::new((self.start, self.end), weekdays.into_iter()).with_priority(self.priority)
+    }
+}
+
+/// Reads the bands of `RoboRate`, filling in their default weekdays, checking that their values are all numbers or
+/// all decimal strings, and checking the band names of its precedence.
+mod band {
+    use std::convert::TryFrom;
+
+    use chrono::{NaiveDateTime, NaiveTime, Weekday};
+    use serde::{de::Error, Deserialize, Deserializer};
+
+    use crate::presets::{weekdays, weekend};
+    use crate::RateUnit;
+
+    use super::{compact, decimal, Meta, RateBand, RateChange, RateRamp, RoboRate, RobotWorkSchema, Shift};
+
+    const MIXED: &str = "band values must be all numbers or all decimal strings";
+
+    /// A `RateBand` as read, remembering whether its value was a decimal string.
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RawBand {
+        #[serde(with = "compact::time")]
+        start: NaiveTime,
+        #[serde(with = "compact::time")]
+        end: NaiveTime,
+        value: decimal::Quoted,
+        #[serde(default)]
+        ramp: Option<RateRamp>,
+        #[serde(default)]
+        priority: i32,
+        #[serde(default, with = "compact::weekdays")]
+        weekdays: Option<Vec<Weekday>>,
+    }
+
+    impl RawBand {
+        fn into_band(self, default_weekdays: impl Iterator<Item=Weekday>) -> RateBand {
+            RateBand {
+                start: self.start,
+                end: self.end,
+                value: self.value.value,
+                ramp: self.ramp,
+                priority: self.priority,
+                weekdays: Some(self.weekdays.unwrap_or_else(|| default_weekdays.collect())),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RawRoboRate {
+        standard_day: RawBand,
+        standard_night: RawBand,
+        extra_day: RawBand,
+        extra_night: RawBand,
+        #[serde(default, deserialize_with = "precedence")]
+        precedence: Vec<String>,
+        #[serde(default = "RateUnit::per_minute")]
+        rate_unit: RateUnit,
+        #[serde(default)]
+        base_fee: u64,
+    }
+
+    impl RawRoboRate {
+        /// The rate, and whether its band values are decimal strings.
+        fn into_rate(self) -> Result<(RoboRate, bool), String> {
+            let decimal = self.standard_day.value.decimal;
+            let bands = [&self.standard_day, &self.standard_night, &self.extra_day, &self.extra_night];
+            if bands.iter().any(|band| band.value.decimal != decimal) {
+                return Err(MIXED.to_string());
+            }
+            if let Some(band) = bands.iter().find(|band| band.priority.checked_abs().map_or(true, |p| p > RateBand::MAX_PRIORITY)) {
+                return Err(format!("band priority {} is beyond {} either way", band.priority, RateBand::MAX_PRIORITY));
+            }
+            let rate = RoboRate {
+                standard_day: self.standard_day.into_band(weekdays()),
+                standard_night: self.standard_night.into_band(weekdays()),
+                extra_day: self.extra_day.into_band(weekend()),
+                extra_night: self.extra_night.into_band(weekend()),
+                precedence: self.precedence,
+                rate_unit: self.rate_unit,
+                base_fee: self.base_fee,
+            };
+            Ok((rate, decimal))
+        }
+    }
+
+    impl TryFrom<RawRoboRate> for RoboRate {
+        type Error = String;
+
+        fn try_from(raw: RawRoboRate) -> Result<Self, Self::Error> {
+            raw.into_rate().map(|(rate, _)| rate)
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RawRateChange {
+        #[serde(with = "compact::datetime")]
+        effective: NaiveDateTime,
+        robo_rate: RawRoboRate,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct RawSchema {
+        shift: Shift,
+        robo_rate: RawRoboRate,
+        #[serde(default)]
+        meta: Option<Meta>,
+        #[serde(default)]
+        rate_changes: Vec<RawRateChange>,
+    }
+
+    impl TryFrom<RawSchema> for RobotWorkSchema {
+        type Error = String;
+
+        fn try_from(raw: RawSchema) -> Result<Self, Self::Error> {
+            let (robo_rate, decimal) = raw.robo_rate.into_rate()?;
+            let rate_changes = raw.rate_changes.into_iter()
+                .map(|change| match change.robo_rate.into_rate()? {
+                    (robo_rate, change_decimal) if change_decimal == decimal => Ok(RateChange { effective: change.effective, robo_rate }),
+                    _ => Err(MIXED.to_string()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(RobotWorkSchema { shift: raw.shift, robo_rate, meta: raw.meta, rate_changes })
+        }
+    }
+
+    pub fn precedence<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+        let precedence = Vec::<String>::deserialize(deserializer)?;
+        let rate = RoboRate::default();
+        let bands = rate.bands();
+        for (i, name) in precedence.iter().enumerate() {
+            if !bands.iter().any(|(known, _)| known == name) {
+                return Err(D::Error::custom(format!("unknown band `{}` in precedence", name)));
+            }
+            if precedence[..i].contains(name) {
+                return Err(D::Error::custom(format!("band `{}` appears twice in precedence", name)));
+            }
+        }
+        Ok(precedence)
+    }
+}
+
+impl RobotWorkSchema {
+    /// Checks the band times of a json schema, and of its rate changes, reporting the first one which is not a
+    /// valid time by its path, e.g. `roboRate.standardNight.end`, which the errors of serde leave out. Anything else,
+    /// including missing times and invalid json, is left to reading the schema.
+    pub fn validate_times_parse(json: &str) -> Result<(), RobotRateError> {
+        let json = match serde_json::from_str::<serde_json::Value>(json) {
+            Ok(json) => json,
+            Err(_) => return Ok(()),
+        };
+        let changes = json["rateChanges"].as_array().into_iter().flatten().enumerate()
+            .map(|(i, change)| (format!("rateChanges[{}].roboRate", i), &change["roboRate"]));
+        for (path, rate) in std::iter::once(("roboRate".to_string(), &json["roboRate"])).chain(changes) {
+            for (band, _) in RoboRate::default().bands().iter() {
+                for field in ["start", "end"].iter() {
+                    let value = &rate[band][field];
+                    let valid = value.as_str().map_or(value.is_null(), |s| s.parse::<NaiveTime>().is_ok());
+                    if !valid {
+                        let value = value.as_str().map_or_else(|| value.to_string(), str::to_string);
+                        return Err(RobotRateError::InvalidTime { path: format!("{}.{}.{}", path, band, field), value });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A rate that changes linearly from `start_value` at the start of a band's window to `end_value` at its end.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateRamp {
+    pub start_value: u64,
+    pub end_value: u64,
+}
+
+impl RateRamp {
+    /// Value of the rate at `datetime`, within the window occurrence `window`.
+    pub fn rate_at(&self, window: (NaiveDateTime, NaiveDateTime), datetime: NaiveDateTime) -> f64 {
+        let len = (window.1 - window.0).num_seconds() as f64;
+        let pos = (datetime - window.0).num_seconds() as f64;
+        let (s, e) = (self.start_value as f64, self.end_value as f64);
+        if len <= 0.0 { s } else { s + (e - s) * pos / len }
+    }
+
+    /// Integrates the ramped rate over the covered interval `[from, to)` of `window`.
+    ///
+    /// The integration is trapezoidal over the covered minutes, i.e. `minutes * (rate(from) + rate(to)) / 2`,
+    /// which is exact since the rate is linear. Unlike flat rates, partial minutes are kept.
+    pub fn integrate(&self, window: (NaiveDateTime, NaiveDateTime), from: NaiveDateTime, to: NaiveDateTime) -> f64 {
+        let minutes = (to - from).num_seconds() as f64 / 60.0;
+        minutes * (self.rate_at(window, from) + self.rate_at(window, to)) / 2.0
+    }
+}
+
+impl RobotWorkTime {
+    /// Builds the robot for a schema, with a time range for each band applying on the days of the band.
+    /// Also returns the per-minute rate of each of its time ranges.
+    pub fn from_schema(schema: &RobotWorkSchema) -> (RobotWorkTime, Vec<u64>) {
+        let rate = &schema.robo_rate;
+        let mut t = RobotWorkTime::new(schema.shift.start, schema.shift.end, rate.time_ranges()).with_rate_unit(rate.rate_unit)
+            .with_base_fee(rate.base_fee);
+        for (idx, (_, band)) in rate.bands().iter().enumerate() {
+            if let Some(ramp) = band.ramp {
+                t = t.with_ramp(idx, ramp);
+            }
+        }
+        for change in schema.rate_changes.iter() {
+            t = t.with_rate_change(change.effective, change.robo_rate.values());
+        }
+        (t, rate.values())
+    }
+
+    /// Like `from_schema`, but `holidays` are billed as weekend days: the extra bands apply on them, and the
+    /// standard bands do not.
+    pub fn from_schema_with_holidays(schema: &RobotWorkSchema, holidays: &[NaiveDate]) -> (RobotWorkTime, Vec<u64>) {
+        let (mut t, rates) = Self::from_schema(schema);
+        // the time ranges follow the bands, see `RoboRate::bands`
+        t.time_range = Arc::new(t.time_range.iter().cloned()
+            .zip(schema.robo_rate.bands().iter())
+            .map(|(range, (name, _))| range.with_holidays(holidays, RoboRate::HOLIDAY_BANDS.contains(name)))
+            .collect());
+        (t, rates)
+    }
+}
+
+mod decimal {
+    use std::fmt;
+
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+    /// A plain integer, or a decimal string with up to two places in hundredths.
+    pub fn hundredths<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        Quoted::deserialize(deserializer).map(|quoted| quoted.value)
+    }
+
+    /// A value read by `hundredths`, and whether it was a decimal string.
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct Quoted {
+        pub value: u64,
+        pub decimal: bool,
+    }
+
+    impl<'de> Deserialize<'de> for Quoted {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            if !deserializer.is_human_readable() {
+                return deserializer.deserialize_u64(HundredthsVisitor);
+            }
+            deserializer.deserialize_any(HundredthsVisitor)
+        }
+    }
+
+    struct HundredthsVisitor;
+
+    impl<'de> Visitor<'de> for HundredthsVisitor {
+        type Value = Quoted;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a non-negative integer, or a decimal string with up to two places")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Quoted, E> {
+            Ok(Quoted { value: v, decimal: false })
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Quoted, E> {
+            parse(v)
+                .map(|value| Quoted { value, decimal: true })
+                .ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+        }
+    }
+
+    /// Parses `"20.50"`, `"20.5"` or `"20"` into 2050 or 2000.
+    pub fn parse(s: &str) -> Option<u64> {
+        let (units, places) = match s.split_once('.') {
+            Some((units, places)) => (units, places),
+            None => (s, ""),
+        };
+        let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if !digits(units) || places.len() > 2 || !(places.is_empty() || digits(places)) || s.ends_with('.') {
+            return None;
+        }
+        let hundredths = format!("{:0<2}", places).parse::<u64>().ok()?;
+        units.parse::<u64>().ok()?.checked_mul(100)?.checked_add(hundredths)
+    }
+}
+
+mod lossless {
+    use std::fmt;
+
+    use serde::de::{self, Deserializer, Visitor};
+
+    /// A plain integer, or a string of its digits, see `ValueFormat`.
+    pub fn integer<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+        if !deserializer.is_human_readable() {
+            return deserializer.deserialize_u64(IntegerVisitor);
+        }
+        deserializer.deserialize_any(IntegerVisitor)
+    }
+
+    struct IntegerVisitor;
+
+    impl<'de> Visitor<'de> for IntegerVisitor {
+        type Value = u64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a non-negative integer, or a string of its digits")
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+            Ok(v)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+            // `parse` would take a leading `+` as well
+            v.parse().ok()
+                .filter(|_| v.bytes().all(|b| b.is_ascii_digit()))
+                .ok_or_else(|| E::invalid_value(de::Unexpected::Str(v), &self))
+        }
+    }
+}
+
+/// chrono types are (de)serialized as strings in human readable formats such as JSON,
+/// and as plain integers in binary formats.
+mod compact {
+    pub mod time {
+        use chrono::{NaiveTime, Timelike};
+        use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer>(time: &NaiveTime, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                time.serialize(serializer)
+            } else {
+                (time.num_seconds_from_midnight(), time.nanosecond()).serialize(serializer)
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+            if deserializer.is_human_readable() {
+                NaiveTime::deserialize(deserializer)
+            } else {
+                let (secs, nano) = <(u32, u32)>::deserialize(deserializer)?;
+                NaiveTime::from_num_seconds_from_midnight_opt(secs, nano).ok_or_else(|| D::Error::custom("invalid time"))
+            }
+        }
+    }
+
+    pub mod weekdays {
+        use chrono::Weekday;
+        use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+        /// As a bit set of the days from Monday in binary formats.
+        pub fn serialize<S: Serializer>(weekdays: &Option<Vec<Weekday>>, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                weekdays.serialize(serializer)
+            } else {
+                weekdays.as_ref()
+                    .map(|weekdays| weekdays.iter().fold(0u8, |bits, day| bits | 1 << day.num_days_from_monday()))
+                    .serialize(serializer)
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<Weekday>>, D::Error> {
+            if deserializer.is_human_readable() {
+                Option::<Vec<Weekday>>::deserialize(deserializer)
+            } else {
+                let bits = Option::<u8>::deserialize(deserializer)?;
+                Ok(bits.map(|bits| crate::presets::all_week().filter(|day| bits & 1 << day.num_days_from_monday() != 0).collect()))
+            }
+        }
+    }
+
+    pub mod datetime {
+        use chrono::NaiveDateTime;
+        use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+        pub fn serialize<S: Serializer>(datetime: &NaiveDateTime, serializer: S) -> Result<S::Ok, S::Error> {
+            if serializer.is_human_readable() {
+                datetime.serialize(serializer)
+            } else {
+                (datetime.timestamp(), datetime.timestamp_subsec_nanos()).serialize(serializer)
+            }
+        }
+
+        /// Besides `2038-01-01T20:15:00`, strings may separate the date and time with a space as in SQL.
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDateTime, D::Error> {
+            if deserializer.is_human_readable() {
+                String::deserialize(deserializer)?.replacen(' ', "T", 1).parse().map_err(D::Error::custom)
+            } else {
+                let (secs, nsecs) = <(i64, u32)>::deserialize(deserializer)?;
+                NaiveDateTime::from_timestamp_opt(secs, nsecs).ok_or_else(|| D::Error::custom("invalid datetime"))
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use super::*;
 
     #[test]
@@ -91,6 +832,419 @@ mod tests {
   }
 }"#;
         let s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
-        dbg!(s);
+        assert_eq!(s, RobotWorkSchema::default());
+        assert_eq!(RoboRate::default().standard_day.value, 20);
+    }
+
+    #[test]
+    fn compact_test() {
+        let line = "shift=2038-01-01T20:15..2038-01-02T04:15 day=07:00-23:00@20/30 night=23:00-07:00@25/35";
+        assert_eq!(line.parse::<RobotWorkSchema>(), Ok(RobotWorkSchema::default()));
+        let with_seconds = "night=23:00:00-07:00:00@25/35  day=07:00-23:00@20/30 shift=2038-01-01T20:15:00..2038-01-02T04:15";
+        assert_eq!(with_seconds.parse::<RobotWorkSchema>(), Ok(RobotWorkSchema::default()));
+
+        let invalid = |part: &str| Err(RobotRateError::InvalidCompactSchema { part: part.to_string() });
+        assert_eq!("day=07:00-23:00@20/30 night=23:00-07:00@25/35".parse::<RobotWorkSchema>(), invalid("shift"));
+        assert_eq!(line.replace("@20/30", "@20").parse::<RobotWorkSchema>(), invalid("day=07:00-23:00@20"));
+        assert_eq!(line.replace("23:00-07:00", "23:00-7h").parse::<RobotWorkSchema>(), invalid("night=23:00-7h@25/35"));
+        assert_eq!(format!("{} day=07:00-23:00@20/30", line).parse::<RobotWorkSchema>(), invalid("day=07:00-23:00@20/30"));
+        assert_eq!(format!("{} rate", line).parse::<RobotWorkSchema>(), invalid("rate"));
+        assert_eq!(
+            invalid("shift").unwrap_err().to_string(),
+            "missing or invalid shift in the compact schema",
+        );
+    }
+
+    #[test]
+    fn value_format_test() {
+        let response = CalculationResponse { value: (1 << 53) + 1, meta: None };
+        assert_eq!(response.to_json(ValueFormat::Number), serde_json::json!({ "value": 9007199254740993u64 }));
+        let json = response.to_json(ValueFormat::String);
+        assert_eq!(json, serde_json::json!({ "value": "9007199254740993" }));
+        assert_eq!(serde_json::from_value::<CalculationResponse>(json).unwrap(), response);
+
+        for invalid in &["+1", "", "1.0", "-1"] {
+            assert!(serde_json::from_value::<CalculationResponse>(serde_json::json!({ "value": invalid })).is_err());
+        }
+    }
+
+    #[test]
+    fn compare_weekdays_test() {
+        use Weekday::*;
+        // Friday 20:15 to Saturday 04:15, with Saturday at the extra rates or at the standard ones
+        let schema = RobotWorkSchema::default();
+        let values = schema.compare_weekdays(&[vec![Mon, Tue, Wed, Thu, Fri], vec![Mon, Tue, Wed, Thu, Fri, Sat]]).unwrap();
+        assert_eq!(values, vec![13725, 13725 - 255 * (35 - 25)]);
+        assert_eq!(values[0], schema.calculate().unwrap().value);
+        assert_eq!(schema.compare_weekdays(&[]), Ok(vec![]));
+    }
+
+    #[test]
+    fn uncovered_start_test() {
+        // the standard day ends before the shift starts on Friday at 20:15, and no other band covers it
+        let mut schema = RobotWorkSchema::default();
+        schema.robo_rate.standard_day.end = NaiveTime::from_hms(20, 0, 0);
+        let uncovered = RobotRateError::UncoveredTime { weekday: Weekday::Fri, time: NaiveTime::from_hms(20, 15, 0) };
+        assert_eq!(schema.calculate(), Err(uncovered.clone()));
+        assert_eq!(schema.compare_weekdays(&[vec![Weekday::Fri]]), Err(uncovered.clone()));
+        let (t, rates) = RobotWorkTime::from_schema(&schema);
+        assert_eq!(t.value(&rates), Err(uncovered.clone()));
+        assert_eq!(t.segments(), Err(uncovered));
+    }
+
+    #[test]
+    fn meta_test() {
+        let json_input = r#"{
+  "shift": {
+      "start": "2038-01-01T20:15:00",
+      "end": "2038-01-02T04:15:00"
+  },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  },
+  "meta": { "robotId": "R2-D2", "costCenter": "CC-42" }
+}"#;
+        let s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        let meta = Meta { robot_id: Some("R2-D2".to_string()), cost_center: Some("CC-42".to_string()) };
+        assert_eq!(s.meta, Some(meta.clone()));
+        assert_eq!(serde_json::from_str::<RobotWorkSchema>(&serde_json::to_string(&s).unwrap()).unwrap(), s);
+
+        let response = s.calculate().unwrap();
+        assert_eq!(response, CalculationResponse { value: 13725, meta: Some(meta) });
+        assert_eq!(
+            serde_json::to_value(&response).unwrap(),
+            serde_json::json!({ "value": 13725, "meta": { "robotId": "R2-D2", "costCenter": "CC-42" } }),
+        );
+
+        let s = RobotWorkSchema { meta: None, ..s };
+        assert_eq!(serde_json::to_string(&s.calculate().unwrap()).unwrap(), r#"{"value":13725}"#);
+    }
+
+    #[test]
+    fn priority_test() {
+        let json_input = r#"{
+  "shift": {
+      "start": "2038-01-01T20:15:00",
+      "end": "2038-01-02T04:15:00"
+  },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "21:00:00", "end": "07:00:00", "value": 25, "priority": 1 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  }
+}"#;
+        let s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        assert_eq!(s.robo_rate.standard_day.priority, 0);
+        assert_eq!(s.robo_rate.standard_night.priority, 1);
+
+        let (t, rates) = RobotWorkTime::from_schema(&s);
+        assert_eq!(rates, vec![20, 25, 30, 35]);
+        // 20:15-21:00 standard day, 21:00-00:00 standard night, then extra night
+        assert_eq!(t.value(&rates).unwrap(), 45 * 20 + 180 * 25 + 255 * 35);
+
+        // priorities that would not leave room for the precedence are rejected
+        let max = RateBand::MAX_PRIORITY;
+        let at_max = json_input.replace(r#""priority": 1"#, &format!(r#""priority": {}"#, -max));
+        assert!(serde_json::from_str::<RobotWorkSchema>(&at_max).is_ok());
+        for beyond in [max + 1, -max - 1, i32::MIN].iter() {
+            let json = json_input.replace(r#""priority": 1"#, &format!(r#""priority": {}"#, beyond));
+            let err = serde_json::from_str::<RobotWorkSchema>(&json).unwrap_err();
+            assert!(err.to_string().contains("beyond"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn ramp_test() {
+        let json_input = r#"{
+  "start": "23:00:00",
+  "end": "07:00:00",
+  "value": 25,
+  "ramp": { "startValue": 25, "endValue": 35 }
+}"#;
+        let band = serde_json::from_str::<RateBand>(json_input).unwrap();
+        assert_eq!(band.ramp, Some(RateRamp { start_value: 25, end_value: 35 }));
+
+        let ramp = band.ramp.unwrap();
+        let window = (NaiveDateTime::from_str("2021-09-06T23:00:00").unwrap(), NaiveDateTime::from_str("2021-09-07T07:00:00").unwrap());
+        assert!((ramp.rate_at(window, NaiveDateTime::from_str("2021-09-07T03:00:00").unwrap()) - 30.0).abs() < 1e-9);
+        assert!((ramp.integrate(window, window.0, window.1) - 30.0 * 480.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn space_separated_shift_test() {
+        let shift = serde_json::from_str::<Shift>(r#"{ "start": "2038-01-01 20:15:00", "end": "2038-01-02T04:15:00" }"#).unwrap();
+        assert_eq!(shift.start, NaiveDateTime::from_str("2038-01-01T20:15:00").unwrap());
+        assert_eq!(shift.end, NaiveDateTime::from_str("2038-01-02T04:15:00").unwrap());
+    }
+
+    #[test]
+    fn rate_change_test() {
+        let json_input = r#"{
+  "shift": { "start": "2038-01-04T22:00:00", "end": "2038-01-05T05:00:00" },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  },
+  "rateChanges": [{
+    "effective": "2038-01-05T02:00:00",
+    "roboRate": {
+      "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+      "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 40 },
+      "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+      "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+    }
+  }]
+}"#;
+        let s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        assert_eq!(s.calculate().unwrap().value, 60 * 20 + 180 * 25 + 180 * 40);
+    }
+
+    #[test]
+    fn holidays_test() {
+        let json_input = r#"{
+  "shift": { "start": "2038-01-05T20:00:00", "end": "2038-01-06T02:00:00" },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  }
+}"#;
+        let s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        assert_eq!(s.calculate().unwrap().value, 180 * 20 + 60 * 25 + 120 * 25);
+
+        // Tuesday is a holiday, Wednesday is not
+        let (t, rates) = RobotWorkTime::from_schema_with_holidays(&s, &[NaiveDate::from_ymd(2038, 1, 5)]);
+        assert_eq!(t.value(&rates), Ok(180 * 30 + 60 * 35 + 120 * 25));
+    }
+
+    #[test]
+    fn applies_at_test() {
+        let json_input = r#"{
+  "shift": { "start": "2038-01-01T20:15:00", "end": "2038-01-02T04:15:00" },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35, "weekdays": ["Fri", "Sat", "Sun"] }
+  }
+}"#;
+        let s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        let rate = &s.robo_rate;
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        // 2038-01-04 is a Monday
+        assert!(rate.standard_day.applies_at(at("2038-01-04T12:00:00")));
+        assert!(!rate.extra_day.applies_at(at("2038-01-04T12:00:00")));
+        assert!(rate.extra_day.applies_at(at("2038-01-03T12:00:00")));
+        // overnight bands are cut at midnight, each part applying on its own day
+        assert!(rate.standard_night.applies_at(at("2038-01-04T23:30:00")));
+        assert!(rate.standard_night.applies_at(at("2038-01-04T06:59:59")));
+        assert!(!rate.standard_night.applies_at(at("2038-01-04T07:00:00")));
+        assert!(rate.extra_night.applies_at(at("2038-01-01T23:30:00")));
+        assert!(!rate.extra_night.applies_at(at("2038-01-04T06:00:00")));
+
+        let (t, _) = RobotWorkTime::from_schema(&s);
+        let mut datetime = at("2038-01-01T00:00:00");
+        while datetime < at("2038-01-08T00:00:00") {
+            assert_eq!(rate.band_at(datetime), crate::find_range(&t.time_range, datetime));
+            datetime += chrono::Duration::minutes(30);
+        }
+    }
+
+    #[test]
+    fn decimal_value_test() {
+        let json_input = r#"{
+  "shift": { "start": "2038-01-01T20:15:00", "end": "2038-01-02T04:15:00" },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": "20.50" },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": "25.25" },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": "30" },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": "35.5" }
+  }
+}"#;
+        let s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        assert_eq!(s.robo_rate.standard_day.value, 2050);
+        assert_eq!(s.robo_rate.extra_night.value, 3550);
+        // 2038-01-01 is a Friday, so the same shift as in `simple_test`, in cents
+        assert_eq!(s.calculate().unwrap().value, 165 * 2050 + 60 * 2525 + 255 * 3550);
+
+        assert!(serde_json::from_str::<RobotWorkSchema>(&json_input.replace("20.50", "20.505")).is_err());
+
+        // a number among decimal strings would be billed as hundredths
+        let mut mixed = serde_json::from_str::<serde_json::Value>(include_str!("../sample_input.json")).unwrap();
+        mixed["roboRate"]["standardDay"]["value"] = "20".into();
+        let err = serde_json::from_value::<RobotWorkSchema>(mixed).unwrap_err();
+        assert!(err.to_string().contains("all numbers or all decimal strings"), "{}", err);
+        let mut changed = serde_json::from_str::<serde_json::Value>(json_input).unwrap();
+        changed["rateChanges"] = serde_json::json!([{ "effective": "2038-01-02T00:00:00", "roboRate": serde_json::from_str::<serde_json::Value>(include_str!("../sample_input.json")).unwrap()["roboRate"] }]);
+        assert!(serde_json::from_value::<RobotWorkSchema>(changed.clone()).is_err());
+        changed["rateChanges"][0]["roboRate"] = changed["roboRate"].clone();
+        assert!(serde_json::from_value::<RobotWorkSchema>(changed).is_ok());
+
+        assert_eq!(decimal::parse("20.5"), Some(2050));
+        assert_eq!(decimal::parse("0.07"), Some(7));
+        for invalid in ["20.", ".5", "-1", "2O", "", "1.-5"].iter() {
+            assert_eq!(decimal::parse(invalid), None);
+        }
+    }
+
+    #[test]
+    fn from_grid_csv_test() {
+        let mut csv = "time,Mon,Tue,Wed,Thu,Fri,Sat,Sun\n".to_string();
+        for slot in 0..48 {
+            let (weekday, weekend) = if (14..46).contains(&slot) { (0, 2) } else { (1, 3) };
+            csv += &format!("{:02}:{:02},{w},{w},{w},{w},{w},{e},{e}\n", slot / 2, slot % 2 * 30, w = weekday, e = weekend);
+        }
+        let ranges = RoboRate::from_grid_csv(&csv).unwrap();
+        assert_eq!(ranges.iter().map(|(tier, _)| *tier).collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        let ranges = ranges.into_iter().map(|(_, range)| range).collect::<Vec<_>>();
+        assert_eq!(ranges, crate::standard_ranges());
+        for (datetime, tier) in [("2021-09-06T06:59:00", 1), ("2021-09-06T07:00:00", 0), ("2021-09-10T23:30:00", 1), ("2021-09-11T23:30:00", 3), ("2021-09-12T12:00:00", 2)].iter() {
+            let datetime = NaiveDateTime::from_str(datetime).unwrap();
+            assert_eq!(crate::find_range(&ranges, datetime), Some(*tier));
+        }
+
+        // uncovered slots, a full day and a bad cell
+        let mut csv = "time,Mon,Sun\n".to_string();
+        for slot in 0..48 {
+            csv += &format!("{:02}:{:02},{},5\n", slot / 2, slot % 2 * 30, if slot < 2 { "" } else { "4" });
+        }
+        let ranges = RoboRate::from_grid_csv(&csv).unwrap();
+        assert_eq!(ranges, vec![
+            (4, TimeRange::new((NaiveTime::from_hms(1, 0, 0), NaiveTime::from_hms(0, 0, 0)), vec![Weekday::Mon].into_iter())),
+            (5, TimeRange::new((NaiveTime::from_hms(0, 0, 0), NaiveTime::from_hms(12, 0, 0)), vec![Weekday::Sun].into_iter())),
+            (5, TimeRange::new((NaiveTime::from_hms(12, 0, 0), NaiveTime::from_hms(0, 0, 0)), vec![Weekday::Sun].into_iter())),
+        ]);
+        assert_eq!(RoboRate::from_grid_csv(&csv.replacen(",4,", ",x,", 1)), Err(RobotRateError::InvalidGrid { line: 4 }));
+        let short = csv.lines().take(48).collect::<Vec<_>>().join("\n");
+        assert_eq!(RoboRate::from_grid_csv(&short), Err(RobotRateError::InvalidGrid { line: 49 }));
+    }
+
+    #[test]
+    fn rate_unit_test() {
+        let json_input = include_str!("../sample_input.json");
+        let schema = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        assert_eq!(schema.robo_rate.rate_unit, RateUnit::PerMinute);
+
+        let mut per_hour = serde_json::from_str::<serde_json::Value>(json_input).unwrap();
+        per_hour["roboRate"]["rateUnit"] = serde_json::json!("perHour");
+        for (band, value) in [("standardDay", 1200), ("standardNight", 1500), ("extraDay", 1800), ("extraNight", 2100)].iter() {
+            per_hour["roboRate"][band]["value"] = serde_json::json!(value);
+        }
+        let per_hour = serde_json::from_value::<RobotWorkSchema>(per_hour).unwrap();
+        assert_eq!(per_hour.calculate().unwrap().value, schema.calculate().unwrap().value);
+
+        // 1250 an hour is 20.83 a minute, which 45 minutes bill 937.5 of
+        let mut odd = per_hour;
+        odd.robo_rate.standard_day.value = 1250;
+        odd.shift.end = NaiveDateTime::from_str("2038-01-01T21:00:00").unwrap();
+        assert_eq!(odd.calculate().unwrap().value, 938);
+        assert_eq!(odd.robo_rate.schedule().apply((odd.shift.start, odd.shift.end)).unwrap().value, 938);
+    }
+
+    #[test]
+    fn schedule_test() {
+        let schema = serde_json::from_str::<RobotWorkSchema>(include_str!("../sample_input.json")).unwrap();
+        let schedule = schema.robo_rate.schedule();
+        let c = schedule.apply((schema.shift.start, schema.shift.end)).unwrap();
+        assert_eq!(c.value, schema.calculate().unwrap().value);
+    }
+
+    #[test]
+    fn validate_times_parse_test() {
+        let json_input = include_str!("../sample_input.json");
+        assert_eq!(RobotWorkSchema::validate_times_parse(json_input), Ok(()));
+        assert_eq!(RobotWorkSchema::validate_times_parse("not json"), Ok(()));
+
+        let mut json = serde_json::from_str::<serde_json::Value>(json_input).unwrap();
+        json["roboRate"]["standardNight"]["end"] = serde_json::json!("25:00:00");
+        let json = json.to_string();
+        assert!(serde_json::from_str::<RobotWorkSchema>(&json).is_err());
+        let e = RobotWorkSchema::validate_times_parse(&json).unwrap_err();
+        assert_eq!(e.to_string(), "roboRate.standardNight.end is not a valid time: '25:00:00'");
+
+        let mut json = serde_json::from_str::<serde_json::Value>(json_input).unwrap();
+        json["rateChanges"] = serde_json::json!([{ "effective": "2038-01-02T00:00:00", "roboRate": json["roboRate"].clone() }]);
+        json["rateChanges"][0]["roboRate"]["extraDay"]["start"] = serde_json::json!(7);
+        assert_eq!(
+            RobotWorkSchema::validate_times_parse(&json.to_string()).unwrap_err().to_string(),
+            "rateChanges[0].roboRate.extraDay.start is not a valid time: '7'",
+        );
+    }
+
+    #[test]
+    fn base_fee_test() {
+        let json_input = include_str!("../sample_input.json");
+        let metered = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        let mut with_fee = serde_json::from_str::<serde_json::Value>(json_input).unwrap();
+        with_fee["roboRate"]["baseFee"] = serde_json::json!(500);
+        let with_fee = serde_json::from_value::<RobotWorkSchema>(with_fee).unwrap();
+        assert_eq!(metered.robo_rate.base_fee, 0);
+        assert_eq!(with_fee.robo_rate.base_fee, 500);
+
+        let metered_value = metered.calculate().unwrap().value;
+        assert_eq!(with_fee.calculate().unwrap().value, 500 + metered_value);
+        let (t, rates) = RobotWorkTime::from_schema(&with_fee);
+        let c = t.calculate(&rates).unwrap();
+        assert_eq!((c.base_fee, c.values.iter().sum::<u64>()), (500, metered_value));
+        let c = with_fee.robo_rate.schedule().apply((with_fee.shift.start, with_fee.shift.end)).unwrap();
+        assert_eq!((c.base_fee, c.value), (500, 500 + metered_value));
+
+        let mut empty = with_fee;
+        empty.shift.end = empty.shift.start;
+        assert_eq!(empty.calculate().unwrap().value, 0);
+    }
+
+    #[test]
+    fn precedence_test() {
+        let json_input = r#"{
+  "shift": { "start": "2038-01-02T10:00:00", "end": "2038-01-02T12:00:00" },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20, "weekdays": ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat"] },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  }
+}"#;
+        // both day bands claim Saturday 2038-01-02
+        let mut s = serde_json::from_str::<RobotWorkSchema>(json_input).unwrap();
+        assert_eq!(s.calculate().unwrap().value, 120 * 30);
+
+        s.robo_rate.precedence = vec!["standardDay".to_string(), "extraDay".to_string()];
+        assert_eq!(s.calculate().unwrap().value, 120 * 20);
+        assert_eq!(s.robo_rate.band_at(s.shift.start), Some(0));
+
+        s.robo_rate.extra_day.priority = 1;
+        assert_eq!(s.calculate().unwrap().value, 120 * 30);
+        assert_eq!(s.robo_rate.band_at(s.shift.start), Some(2));
+
+        // repeating a band does not lift it past a higher priority
+        s.robo_rate.precedence = std::iter::repeat("standardDay".to_string()).take(8).chain(Some("extraDay".to_string())).collect();
+        assert_eq!(s.robo_rate.band_at(s.shift.start), Some(2));
+    }
+
+    #[test]
+    fn invalid_precedence_test() {
+        let json_input = |precedence: &str| format!(r#"{{
+  "shift": {{ "start": "2038-01-02T10:00:00", "end": "2038-01-02T12:00:00" }},
+  "roboRate": {{
+    "standardDay": {{ "start": "07:00:00", "end": "23:00:00", "value": 20 }},
+    "standardNight": {{ "start": "23:00:00", "end": "07:00:00", "value": 25 }},
+    "extraDay": {{ "start": "07:00:00", "end": "23:00:00", "value": 30 }},
+    "extraNight": {{ "start": "23:00:00", "end": "07:00:00", "value": 35 }},
+    "precedence": {}
+  }}
+}}"#, precedence);
+        assert!(serde_json::from_str::<RobotWorkSchema>(&json_input(r#"["extraDay", "standardDay"]"#)).is_ok());
+        let err = serde_json::from_str::<RobotWorkSchema>(&json_input(r#"["extraDay", "holiday"]"#)).unwrap_err();
+        assert!(err.to_string().contains("unknown band `holiday`"), "{}", err);
+        let err = serde_json::from_str::<RobotWorkSchema>(&json_input(r#"["extraDay", "standardDay", "extraDay"]"#)).unwrap_err();
+        assert!(err.to_string().contains("band `extraDay` appears twice"), "{}", err);
     }
 }
@@ -0,0 +1,597 @@
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::{cycle_start, find_range, merge_windows, uncovered_between, windows_between, RobotRateError, RobotWorkTime};
+use crate::presets::all_week;
+
+/// A span of the shift during which the robot status does not change.
+/// `status` is the index of the time range the robot is billed at, or `None` when it is not working.
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Segment {
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+    pub status: Option<usize>,
+    pub kind: SegmentKind,
+}
+
+#[derive(Eq, PartialEq, Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SegmentKind {
+    Work,
+    Break,
+    Maintenance,
+    OffDuty,
+}
+
+/// The robot status at some instant, and how long it lasts.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub struct CurrentStatus {
+    pub tier: Option<usize>,
+    pub since: NaiveDateTime,
+    pub until: NaiveDateTime,
+    /// The status following `until`, or `None` if the shift ends at `until` or the robot stops working.
+    pub next_tier: Option<usize>,
+}
+
+/// Why an instant is billed as it is, see `RobotWorkTime::explain_instant`.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstantExplanation {
+    pub weekday: Weekday,
+    /// The time range billed, or `None` if the robot is not working then.
+    pub tier: Option<usize>,
+    /// The time range applying at the instant, whether or not the robot works then.
+    pub range: Option<RangeMatch>,
+    /// Time ranges covering the time of day on this weekday that a holiday rules out.
+    pub excluded: Vec<usize>,
+    /// What the robot is doing, or `None` outside the shift.
+    pub kind: Option<SegmentKind>,
+}
+
+/// A time range applying at an instant.
+#[derive(Eq, PartialEq, Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeMatch {
+    pub idx: usize,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub weekdays: Vec<Weekday>,
+    /// Whether the range applies for a holiday rather than for its weekdays.
+    pub holiday: bool,
+}
+
+impl Segment {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// Whether the robot is in maintenance or off duty.
+    pub fn is_offline(&self) -> bool {
+        self.kind == SegmentKind::Maintenance || self.kind == SegmentKind::OffDuty
+    }
+}
+
+impl RobotWorkTime {
+    /// All segments of the shift in chronological order. They stop short of the shift end if walking the shift
+    /// runs out of representable dates. Fails as `try_into_iter` does.
+    pub fn segments(&self) -> Result<Vec<Segment>, RobotRateError> {
+        let mut ret: Vec<Segment> = vec![];
+        for segment in self.clone().into_segments()? {
+            match ret.last_mut() {
+                Some(last) if last.kind == segment.kind && segment.is_offline() && last.end == segment.start => {
+                    last.end = segment.end;
+                }
+                _ => ret.push(segment),
+            }
+        }
+        Ok(ret)
+    }
+
+    /// The segments of the shift billed at the time range `tier`, in chronological order.
+    pub fn segments_in_tier(&self, tier: usize) -> Result<Vec<Segment>, RobotRateError> {
+        Ok(self.segments()?.into_iter().filter(|segment| segment.status == Some(tier)).collect())
+    }
+
+    /// Lazily walks the segments of the shift. Unlike `segments`, maintenance or off-duty time spanning a
+    /// transition or a break is reported as consecutive segments. Fails as `try_into_iter` does.
+    pub fn into_segments(self) -> Result<impl Iterator<Item=Segment>, RobotRateError> {
+        let points = self.clone().try_into_iter()?;
+        Ok(pair_points(points).flat_map(move |segment| self.carve_maintenance(segment)))
+    }
+
+    /// Splits `segment` into the parts outside and inside maintenance, and then off-duty time.
+    fn carve_maintenance(&self, segment: Segment) -> Vec<Segment> {
+        let windows = self.maintenance_between(segment.start, segment.end);
+        carve(segment, windows, SegmentKind::Maintenance).into_iter()
+            .flat_map(|part| match part.kind {
+                SegmentKind::Maintenance => vec![part],
+                _ => carve(part, self.off_duty_between(part.start, part.end), SegmentKind::OffDuty),
+            })
+            .collect()
+    }
+
+    /// Off-duty intervals within `[from, to)`, sorted and merged, including the time no range covers when
+    /// auto-advancing.
+    fn off_duty_between(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let windows = windows_between(&self.off_duty_windows, from, to);
+        if !self.auto_advance {
+            return windows;
+        }
+        let mut pieces = uncovered_between(&self.time_range, from, to);
+        pieces.extend(windows);
+        merge_windows(pieces)
+    }
+
+    /// Maintenance intervals within `[from, to)`, sorted and merged.
+    fn maintenance_between(&self, from: NaiveDateTime, to: NaiveDateTime) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        windows_between(&self.maintenance_windows, from, to)
+    }
+
+    /// The segment active at `datetime`, computed from the time ranges, the break cadence and the maintenance
+    /// windows directly. With breaks snapped to boundaries or split, with off-duty time, or when auto-advancing, it
+    /// walks the shift instead.
+    pub fn active_segment(&self, datetime: NaiveDateTime) -> Option<Segment> {
+        if datetime < self.start || datetime >= self.end {
+            return None;
+        }
+        if self.break_snap_tolerance().is_some() || self.split_breaks || self.auto_advance || !self.off_duty_windows.is_empty() {
+            return self.segments().ok()?.into_iter().find(|segment| segment.start <= datetime && datetime < segment.end);
+        }
+
+        let maintenance = self.maintenance_between(self.start, self.end);
+        if let Some(&(s, e)) = maintenance.iter().find(|(s, e)| *s <= datetime && datetime < *e) {
+            return Some(Segment { start: s, end: e, status: None, kind: SegmentKind::Maintenance });
+        }
+        let after = maintenance.iter().rev().find(|(_, e)| *e <= datetime).map_or(self.start, |m| m.1);
+        let before = maintenance.iter().find(|(s, _)| *s > datetime).map_or(self.end, |m| m.0);
+
+        let work_start = cycle_start(self.break_anchor, self.work_duration, self.rest_duration, datetime)?;
+        let work_end = work_start.checked_add_signed(self.work_duration)?;
+        if datetime >= work_end {
+            return Some(Segment {
+                start: work_end.max(after),
+                end: work_end.checked_add_signed(self.rest_duration)?.min(before),
+                status: None,
+                kind: SegmentKind::Break,
+            });
+        }
+
+        let idx = find_range(&self.time_range, datetime)?;
+        let piece_start = self.time_range.iter().filter_map(|range| range.prev_boundary_at(datetime)).max()?;
+        let piece_end = self.time_range.iter().filter_map(|range| range.next_boundary_after(datetime)).min()?;
+        Some(Segment {
+            start: piece_start.max(work_start).max(after),
+            end: piece_end.min(work_end).min(before),
+            status: Some(idx),
+            kind: SegmentKind::Work,
+        })
+    }
+
+    /// The status at `now`, together with the remaining time in it and the status after, without walking the shift.
+    pub fn current_status(&self, now: NaiveDateTime) -> Option<CurrentStatus> {
+        let segment = self.active_segment(now)?;
+        Some(CurrentStatus {
+            tier: segment.status,
+            since: segment.start,
+            until: segment.end,
+            next_tier: self.status_at(segment.end),
+        })
+    }
+
+    /// The next time point after `datetime` within the shift, i.e. the end of the active segment, with the status
+    /// from then on, `None` at the shift end or from the start of a break. Like `active_segment`, it only walks the
+    /// shift if breaks may move off the cadence.
+    pub fn first_transition_after(&self, datetime: NaiveDateTime) -> Option<(NaiveDateTime, Option<usize>)> {
+        let status = self.current_status(datetime)?;
+        Some((status.until, status.next_tier))
+    }
+
+    /// The status at every `every` from the shift start. Each sample stands for the interval up to the next one,
+    /// so the last sample may stand for a partial interval, cut by the shift end.
+    pub fn sample(&self, every: Duration) -> Vec<(NaiveDateTime, Option<usize>)> {
+        let mut samples = vec![];
+        if every <= Duration::zero() {
+            return samples;
+        }
+        let mut datetime = self.start;
+        while datetime < self.end {
+            samples.push((datetime, self.status_at(datetime)));
+            datetime = match datetime.checked_add_signed(every) {
+                Some(next) => next,
+                None => break,
+            };
+        }
+        samples
+    }
+
+    /// Explains the billing of `datetime`: the time range applying and how it matched, and what the robot is doing.
+    pub fn explain_instant(&self, datetime: NaiveDateTime) -> InstantExplanation {
+        let segment = self.active_segment(datetime);
+        let date = datetime.date();
+        let range = find_range(&self.time_range, datetime).map(|idx| {
+            let range = &self.time_range[idx];
+            RangeMatch {
+                idx,
+                start: range.start,
+                end: range.end,
+                weekdays: all_week().filter(|d| range.valid_weekdays.contains(d)).collect(),
+                holiday: !range.valid_weekdays.contains(&date.weekday()),
+            }
+        });
+        let excluded = self.time_range.iter().enumerate()
+            .filter(|(_, range)| range.valid_weekdays.contains(&date.weekday()) && range.holidays.get(&date) == Some(&false))
+            .filter(|(_, range)| range.covers_time(datetime.time()))
+            .map(|(idx, _)| idx)
+            .collect();
+        InstantExplanation {
+            weekday: date.weekday(),
+            tier: segment.and_then(|segment| segment.status),
+            range,
+            excluded,
+            kind: segment.map(|segment| segment.kind),
+        }
+    }
+
+    /// The index of the time range billed at `datetime`, or `None` during a break or outside the shift.
+    pub fn status_at(&self, datetime: NaiveDateTime) -> Option<usize> {
+        self.active_segment(datetime).and_then(|segment| segment.status)
+    }
+}
+
+/// Splits `segment` into the parts outside and inside `windows`, the latter of `kind`.
+fn carve(segment: Segment, windows: Vec<(NaiveDateTime, NaiveDateTime)>, kind: SegmentKind) -> Vec<Segment> {
+    let mut ret = vec![];
+    let mut cur = segment.start;
+    for (s, e) in windows {
+        if cur < s {
+            ret.push(Segment { start: cur, end: s, ..segment });
+        }
+        ret.push(Segment { start: s, end: e, status: None, kind });
+        cur = e;
+    }
+    if cur < segment.end {
+        ret.push(Segment { start: cur, ..segment });
+    }
+    ret
+}
+
+/// Pairs consecutive points into segments. Coincident points yield no segment, the status from the last of them
+/// carrying on to the next point.
+fn pair_points(points: impl Iterator<Item=(NaiveDateTime, Option<usize>)>) -> impl Iterator<Item=Segment> {
+    let mut points = points.peekable();
+    std::iter::from_fn(move || loop {
+        let (start, status) = points.next()?;
+        let &(end, _) = points.peek()?;
+        if start == end {
+            continue;
+        }
+        let kind = if status.is_some() { SegmentKind::Work } else { SegmentKind::Break };
+        return Some(Segment { start, end, status, kind });
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::str::FromStr;
+
+    use chrono::{NaiveTime, Weekday};
+
+    use crate::presets::{all_week, single, weekdays, weekend};
+    use crate::{standard_ranges, TimeRange};
+
+    use super::*;
+
+    fn base() -> RobotWorkTime {
+        RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap(),
+            standard_ranges(),
+        )
+    }
+
+    /// Compares `segments`, one json object per line, with the snapshot `name` in `src/snapshots`. With
+    /// `UPDATE_SNAPSHOTS` set, it writes the snapshot instead.
+    fn assert_snapshot(name: &str, segments: &[Segment]) {
+        let actual = segments.iter().map(|segment| serde_json::to_string(segment).unwrap() + "\n").collect::<String>();
+        let path = Path::new(file!()).with_file_name("snapshots").join(format!("{}.jsonl", name));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("cannot read {}: {}, run with UPDATE_SNAPSHOTS=1 to create it", path.display(), e));
+        assert_eq!(actual, expected, "segments differ from {}", path.display());
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        assert_snapshot("base", &base().segments().unwrap());
+        let t = RobotWorkTime::new(at("2021-09-10T00:01:00"), at("2021-09-12T00:30:00"), standard_ranges());
+        assert_snapshot("start_early", &t.segments().unwrap());
+        let t = RobotWorkTime::new(at("2021-09-10T23:01:00"), at("2021-09-11T12:55:00"), standard_ranges());
+        assert_snapshot("start_late", &t.segments().unwrap());
+        let t = RobotWorkTime::new(at("2021-09-10T23:01:00"), at("2021-09-11T20:55:00"), vec![
+            TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekdays()),
+            TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays()),
+            TimeRange::new((NaiveTime::from_hms(3, 0, 0), NaiveTime::from_hms(15, 0, 0)), weekend()),
+            TimeRange::new((NaiveTime::from_hms(15, 0, 0), NaiveTime::from_hms(3, 0, 0)), weekend()),
+        ]);
+        assert_snapshot("complex_scheme", &t.segments().unwrap());
+    }
+
+    #[test]
+    fn segments_in_tier_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let t = RobotWorkTime::new(at("2021-09-10T23:01:00"), at("2021-09-11T20:55:00"), vec![
+            TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), weekdays()),
+            TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), weekdays()),
+            TimeRange::new((NaiveTime::from_hms(3, 0, 0), NaiveTime::from_hms(15, 0, 0)), weekend()),
+            TimeRange::new((NaiveTime::from_hms(15, 0, 0), NaiveTime::from_hms(3, 0, 0)), weekend()),
+        ]);
+        let intervals = |tier| {
+            let segments = t.segments_in_tier(tier).unwrap();
+            assert!(segments.iter().all(|segment| segment.status == Some(tier) && segment.kind == SegmentKind::Work));
+            segments.iter().map(|segment| (segment.start, segment.end)).collect::<Vec<_>>()
+        };
+        assert_eq!(intervals(3), vec![
+            (at("2021-09-11T00:00:00"), at("2021-09-11T03:00:00")),
+            (at("2021-09-11T15:00:00"), at("2021-09-11T16:01:00")),
+            (at("2021-09-11T17:01:00"), at("2021-09-11T20:55:00")),
+        ]);
+        assert_eq!(intervals(2), vec![
+            (at("2021-09-11T03:00:00"), at("2021-09-11T07:01:00")),
+            (at("2021-09-11T08:01:00"), at("2021-09-11T15:00:00")),
+        ]);
+        assert_eq!(intervals(1), vec![(at("2021-09-10T23:01:00"), at("2021-09-11T00:00:00"))]);
+        assert_eq!(intervals(0), vec![]);
+        assert_eq!(intervals(4), vec![]);
+    }
+
+    #[test]
+    fn first_transition_after_test() {
+        let t = base();
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        assert_eq!(t.first_transition_after(at("2021-09-05T22:30:00")), Some((at("2021-09-05T23:00:00"), Some(3))));
+        assert_eq!(t.first_transition_after(at("2021-09-05T23:00:00")), Some((at("2021-09-06T00:00:00"), Some(1))));
+        assert_eq!(t.first_transition_after(at("2021-09-06T05:00:00")), Some((at("2021-09-06T06:00:00"), None)));
+        assert_eq!(t.first_transition_after(at("2021-09-06T06:30:00")), Some((at("2021-09-06T07:00:00"), Some(0))));
+        assert_eq!(t.first_transition_after(at("2021-09-06T12:00:00")), Some((t.end, None)));
+        assert_eq!(t.first_transition_after(t.end), None);
+    }
+
+    #[test]
+    fn active_segment_test() {
+        let t = base();
+        assert_eq!(t.active_segment(NaiveDateTime::from_str("2021-09-05T22:30:00").unwrap()), Some(Segment {
+            start: NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
+            end: NaiveDateTime::from_str("2021-09-05T23:00:00").unwrap(),
+            status: Some(2),
+            kind: SegmentKind::Work,
+        }));
+        assert_eq!(t.active_segment(NaiveDateTime::from_str("2021-09-06T06:30:00").unwrap()), Some(Segment {
+            start: NaiveDateTime::from_str("2021-09-06T06:00:00").unwrap(),
+            end: NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            status: None,
+            kind: SegmentKind::Break,
+        }));
+        assert_eq!(t.status_at(NaiveDateTime::from_str("2021-09-05T23:00:00").unwrap()), Some(3));
+        assert_eq!(t.active_segment(NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap()), None);
+    }
+
+    #[test]
+    fn sub_second_active_segment_test() {
+        // the anchor lies three cycles of 1.1s and 200ms before the start
+        let start = NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap();
+        let ms = Duration::milliseconds;
+        let t = RobotWorkTime::builder()
+            .shift(start, start + Duration::seconds(2))
+            .add_range(TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()))
+            .break_policy(ms(500), ms(600))
+            .build()
+            .unwrap()
+            .with_break_anchor(start - ms(3500));
+        assert_eq!(t.active_segment(start + ms(100)), Some(Segment { start, end: start + ms(300), status: Some(0), kind: SegmentKind::Work }));
+        assert_eq!(t.active_segment(start + ms(500)), Some(Segment { start: start + ms(300), end: start + ms(900), status: None, kind: SegmentKind::Break }));
+        assert_eq!(t.active_segment(start + ms(1000)), t.segments().unwrap().get(2).copied());
+    }
+
+    #[test]
+    fn active_segment_matches_segments_test() {
+        let t = base();
+        let segments = t.segments().unwrap();
+        let mut datetime = NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap();
+        while datetime < NaiveDateTime::from_str("2021-09-06T12:59:00").unwrap() {
+            let expected = segments.iter().find(|s| s.start <= datetime && datetime < s.end).copied();
+            assert_eq!(t.active_segment(datetime), expected);
+            datetime += Duration::minutes(15);
+        }
+    }
+
+    #[test]
+    fn current_status_test() {
+        let t = base();
+        assert_eq!(t.current_status(NaiveDateTime::from_str("2021-09-05T22:30:00").unwrap()), Some(CurrentStatus {
+            tier: Some(2),
+            since: NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
+            until: NaiveDateTime::from_str("2021-09-05T23:00:00").unwrap(),
+            next_tier: Some(3),
+        }));
+        let status = t.current_status(NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap()).unwrap();
+        assert_eq!((status.tier, status.until, status.next_tier), (Some(0), t.end, None));
+    }
+
+    #[test]
+    fn shift_within_break_test() {
+        let anchor = NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap();
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T06:15:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T06:45:00").unwrap(),
+            base().time_range,
+        ).with_break_anchor(anchor);
+        let rest = Segment { start: t.start, end: t.end, status: None, kind: SegmentKind::Break };
+        assert_eq!(t.clone().into_iter().collect::<Vec<_>>(), vec![(t.start, None), (t.end, None)]);
+        assert_eq!(t.segments().unwrap(), vec![rest]);
+        assert_eq!(t.active_segment(NaiveDateTime::from_str("2021-09-06T06:30:00").unwrap()), Some(rest));
+        assert_eq!(t.value(&[20, 25, 30, 35]).unwrap(), 0);
+
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T06:30:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap(),
+            base().time_range,
+        ).with_break_anchor(anchor);
+        assert_eq!(t.clone().into_iter().collect::<Vec<_>>(), vec![
+            (NaiveDateTime::from_str("2021-09-06T06:30:00").unwrap(), None),
+            (NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(), Some(0)),
+            (NaiveDateTime::from_str("2021-09-06T08:00:00").unwrap(), None),
+        ]);
+        assert_eq!(t.value(&[20, 25, 30, 35]).unwrap(), 60 * 20);
+    }
+
+    #[test]
+    fn explain_instant_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let t = RobotWorkTime::new(at("2021-09-10T22:00:00"), at("2021-09-11T08:00:00"), standard_ranges());
+        let e = t.explain_instant(at("2021-09-11T02:30:00"));
+        assert_eq!(e, InstantExplanation {
+            weekday: Weekday::Sat,
+            tier: Some(3),
+            range: Some(RangeMatch {
+                idx: 3,
+                start: NaiveTime::from_hms(23, 0, 0),
+                end: NaiveTime::from_hms(7, 0, 0),
+                weekdays: vec![Weekday::Sat, Weekday::Sun],
+                holiday: false,
+            }),
+            excluded: vec![],
+            kind: Some(SegmentKind::Work),
+        });
+        assert_eq!(t.explain_instant(at("2021-09-11T06:30:00")).kind, Some(SegmentKind::Break));
+        assert_eq!(t.explain_instant(at("2021-09-11T06:30:00")).tier, None);
+        assert_eq!(t.explain_instant(at("2021-09-11T09:00:00")).kind, None);
+
+        // a Saturday holiday billed as a weekday
+        let holiday = [at("2021-09-11T00:00:00").date()];
+        let mut ranges = standard_ranges();
+        ranges[1] = ranges[1].clone().with_holidays(&holiday, true);
+        ranges[3] = ranges[3].clone().with_holidays(&holiday, false);
+        let e = RobotWorkTime::new(t.start, t.end, ranges).explain_instant(at("2021-09-11T02:30:00"));
+        assert_eq!((e.tier, e.range.unwrap().holiday, e.excluded), (Some(1), true, vec![3]));
+    }
+
+    #[test]
+    fn coincident_points_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        let points = vec![
+            (at("2021-09-06T06:00:00"), Some(1)),
+            (at("2021-09-06T07:00:00"), None),
+            (at("2021-09-06T07:00:00"), Some(0)),
+            (at("2021-09-06T08:00:00"), Some(0)),
+            (at("2021-09-06T08:00:00"), None),
+        ];
+        assert_eq!(pair_points(points.into_iter()).collect::<Vec<_>>(), vec![
+            Segment { start: at("2021-09-06T06:00:00"), end: at("2021-09-06T07:00:00"), status: Some(1), kind: SegmentKind::Work },
+            Segment { start: at("2021-09-06T07:00:00"), end: at("2021-09-06T08:00:00"), status: Some(0), kind: SegmentKind::Work },
+        ]);
+
+        let t = base().with_break_tie(crate::BreakTie::TransitionFirst);
+        let segments = t.segments().unwrap();
+        assert!(segments.iter().all(|s| s.start < s.end));
+        assert!(segments.windows(2).all(|w| w[0].end == w[1].start));
+    }
+
+    #[test]
+    fn sample_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T00:00:00").unwrap(),
+            base().time_range,
+        );
+        assert_eq!(t.sample(Duration::minutes(30)), vec![
+            (NaiveDateTime::from_str("2021-09-05T22:00:00").unwrap(), Some(2)),
+            (NaiveDateTime::from_str("2021-09-05T22:30:00").unwrap(), Some(2)),
+            (NaiveDateTime::from_str("2021-09-05T23:00:00").unwrap(), Some(3)),
+            (NaiveDateTime::from_str("2021-09-05T23:30:00").unwrap(), Some(3)),
+        ]);
+
+        let t = RobotWorkTime::new(t.start, NaiveDateTime::from_str("2021-09-06T00:10:00").unwrap(), base().time_range);
+        let samples = t.sample(Duration::minutes(30));
+        assert_eq!(samples.len(), 5);
+        assert_eq!(samples[4], (NaiveDateTime::from_str("2021-09-06T00:00:00").unwrap(), Some(1)));
+        assert_eq!(t.sample(Duration::zero()), vec![]);
+    }
+
+    #[test]
+    fn maintenance_window_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-07T23:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-08T07:00:00").unwrap(),
+            base().time_range,
+        );
+        let rates = [20, 25, 30, 35];
+        assert_eq!(t.value(&rates).unwrap(), 480 * 25);
+
+        let t = t.with_maintenance_windows(vec![
+            TimeRange::new((NaiveTime::from_hms(2, 0, 0), NaiveTime::from_hms(3, 0, 0)), single(Weekday::Wed)),
+        ]);
+        assert_eq!(t.value(&rates).unwrap(), 420 * 25);
+
+        let maintenance = Segment {
+            start: NaiveDateTime::from_str("2021-09-08T02:00:00").unwrap(),
+            end: NaiveDateTime::from_str("2021-09-08T03:00:00").unwrap(),
+            status: None,
+            kind: SegmentKind::Maintenance,
+        };
+        assert!(t.segments().unwrap().contains(&maintenance));
+        assert_eq!(t.clone().into_segments().unwrap().collect::<Vec<_>>(), t.segments().unwrap());
+        assert_eq!(t.active_segment(NaiveDateTime::from_str("2021-09-08T02:30:00").unwrap()), Some(maintenance));
+        assert_eq!(t.active_segment(NaiveDateTime::from_str("2021-09-08T01:30:00").unwrap()).unwrap().end, maintenance.start);
+    }
+
+    #[test]
+    fn off_duty_test() {
+        let t = RobotWorkTime::new(
+            NaiveDateTime::from_str("2021-09-06T07:00:00").unwrap(),
+            NaiveDateTime::from_str("2021-09-06T19:00:00").unwrap(),
+            standard_ranges(),
+        );
+        let first_break = |t: &RobotWorkTime| t.segments().unwrap().into_iter().find(|s| s.kind == SegmentKind::Break).unwrap().start;
+        assert_eq!(first_break(&t), NaiveDateTime::from_str("2021-09-06T15:00:00").unwrap());
+
+        // two hours off duty push the break back by two hours
+        let t = t.with_off_duty_windows(vec![
+            TimeRange::new((NaiveTime::from_hms(9, 0, 0), NaiveTime::from_hms(11, 0, 0)), all_week()),
+        ]);
+        assert_eq!(first_break(&t), NaiveDateTime::from_str("2021-09-06T17:00:00").unwrap());
+        assert!(t.segments().unwrap().contains(&Segment {
+            start: NaiveDateTime::from_str("2021-09-06T09:00:00").unwrap(),
+            end: NaiveDateTime::from_str("2021-09-06T11:00:00").unwrap(),
+            status: None,
+            kind: SegmentKind::OffDuty,
+        }));
+        assert_eq!(t.value(&[20, 25, 30, 35]), Ok(540 * 20));
+        assert_eq!(t.status_at(NaiveDateTime::from_str("2021-09-06T16:30:00").unwrap()), Some(0));
+    }
+
+    #[test]
+    fn maintenance_over_break_test() {
+        let t = base().with_maintenance_windows(vec![
+            TimeRange::new((NaiveTime::from_hms(5, 30, 0), NaiveTime::from_hms(6, 30, 0)), all_week()),
+        ]);
+        let segments = t.segments().unwrap();
+        assert_eq!(segments.iter().filter(|s| s.kind == SegmentKind::Maintenance).count(), 1);
+        assert_eq!(segments.iter().map(|s| s.duration()).fold(Duration::zero(), |a, b| a + b), t.end - t.start);
+
+        let mut datetime = t.start;
+        while datetime < t.end {
+            let expected = segments.iter().find(|s| s.start <= datetime && datetime < s.end).copied();
+            assert_eq!(t.active_segment(datetime), expected);
+            datetime += Duration::minutes(15);
+        }
+    }
+}
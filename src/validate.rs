@@ -0,0 +1,334 @@
+use std::fmt;
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Weekday};
+use serde::Serialize;
+
+use crate::{cycle_start, RobotWorkTime, TimeRange};
+use crate::builder::first_uncovered;
+use crate::schema::RobotWorkSchema;
+
+/// A suspicious but accepted configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// The band starts and ends at the same time. It is not read as a 24-hour band, and never applies.
+    EmptyBand { band: &'static str },
+    /// The robot works the whole shift without a break, as the shift is shorter than its work duration.
+    NoBreaks { shift: Duration, work: Duration },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::EmptyBand { band } => write!(f, "{} starts and ends at the same time, so it never applies", band),
+            Warning::NoBreaks { shift, work } => write!(
+                f, "no breaks inserted over a shift of {} minutes, as the robot works {} minutes between breaks",
+                shift.num_minutes(), work.num_minutes(),
+            ),
+        }
+    }
+}
+
+impl RobotWorkSchema {
+    pub fn warnings(&self) -> Vec<Warning> {
+        self.robo_rate.bands().iter()
+            .filter(|(_, band)| band.start == band.end)
+            .map(|(name, _)| Warning::EmptyBand { band: name })
+            .collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    /// The configuration works, but likely not as intended.
+    Warning,
+    /// The configuration cannot be calculated.
+    Error,
+}
+
+/// A problem with a schema, and how to fix it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    pub severity: Severity,
+    pub message: String,
+    pub suggestion: String,
+}
+
+impl RobotWorkSchema {
+    /// Every problem found with the schema: gaps between bands, bands overlapping at the same priority, and
+    /// the warnings.
+    pub fn lint(&self) -> Vec<Lint> {
+        let bands = self.robo_rate.bands();
+        let (robot, _) = RobotWorkTime::from_schema(self);
+        let mut lints = vec![];
+        if let Some((weekday, time)) = first_uncovered(&robot.time_range) {
+            lints.push(Lint {
+                severity: Severity::Error,
+                message: format!("no band covers {} {}", weekday, time),
+                suggestion: format!("make the band ending at {} end where the next band starts", time),
+            });
+        }
+        if let Some((a, b, weekday, time)) = first_overlap(&robot.time_range) {
+            lints.push(Lint {
+                severity: Severity::Warning,
+                message: format!("{} and {} both apply on {} {}, where {} wins", bands[a].0, bands[b].0, weekday, time, bands[b].0),
+                suggestion: format!("give {} a higher priority, or move its start or end", bands[a].0),
+            });
+        }
+        for warning in self.warnings().into_iter().chain(robot.warnings(robot.work_duration)) {
+            let suggestion = match &warning {
+                Warning::EmptyBand { band } => format!("remove {}, or give it distinct start and end times", band),
+                Warning::NoBreaks { .. } => "shorten the work duration between breaks".to_string(),
+            };
+            lints.push(Lint { severity: Severity::Warning, message: warning.to_string(), suggestion });
+        }
+        lints
+    }
+}
+
+/// Part of a day during which the bands are wrong, on each of `weekdays`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Interval {
+    pub weekdays: Vec<Weekday>,
+    pub start: NaiveTime,
+    /// `00:00:00` where the interval lasts until the end of the day.
+    pub end: NaiveTime,
+    /// The bands overlapping at the same priority, none for a gap.
+    pub bands: Vec<&'static str>,
+}
+
+/// Where no band applies, and where bands overlap at the same priority.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Coverage {
+    pub gaps: Vec<Interval>,
+    pub overlaps: Vec<Interval>,
+}
+
+impl RobotWorkSchema {
+    /// Every gap and overlap of the bands over the week, each part of a day listed once with all the weekdays
+    /// it is wrong on.
+    pub fn coverage(&self) -> Coverage {
+        let bands = self.robo_rate.bands();
+        let (robot, _) = RobotWorkTime::from_schema(self);
+        let mut gaps: Vec<Interval> = vec![];
+        let mut overlaps: Vec<Interval> = vec![];
+        for (weekday, start, end, tied) in week_walk(&robot.time_range) {
+            let intervals = match tied.len() {
+                0 => &mut gaps,
+                1 => continue,
+                _ => &mut overlaps,
+            };
+            let names = tied.iter().map(|idx| bands[*idx].0).collect::<Vec<_>>();
+            match intervals.iter_mut().find(|i| i.start == start && i.end == end && i.bands == names) {
+                Some(interval) => interval.weekdays.push(weekday),
+                None => intervals.push(Interval { weekdays: vec![weekday], start, end, bands: names }),
+            }
+        }
+        Coverage { gaps, overlaps }
+    }
+}
+
+/// The first time of the week, starting from Monday midnight, at which two of `time_ranges` apply at the same
+/// priority, with the indices of the two.
+fn first_overlap(time_ranges: &[TimeRange]) -> Option<(usize, usize, Weekday, NaiveTime)> {
+    week_walk(time_ranges).into_iter()
+        .find(|(_, _, _, tied)| tied.len() > 1)
+        .map(|(weekday, start, _, tied)| (tied[tied.len() - 2], tied[tied.len() - 1], weekday, start))
+}
+
+/// Splits the week, starting from Monday midnight, into the parts of days during which the same of `time_ranges`
+/// apply at the top priority, with their indices.
+pub(crate) fn week_walk(time_ranges: &[TimeRange]) -> Vec<(Weekday, NaiveTime, NaiveTime, Vec<usize>)> {
+    let monday = NaiveDate::from_ymd(2021, 9, 6).and_hms(0, 0, 0);
+    let week_end = monday + Duration::weeks(1);
+    let mut ret: Vec<(Weekday, NaiveTime, NaiveTime, Vec<usize>)> = vec![];
+    let mut t = monday;
+    while t < week_end {
+        let covering = time_ranges.iter().enumerate()
+            .filter(|(_, range)| range.contains(t))
+            .collect::<Vec<_>>();
+        let top = covering.iter().map(|(_, range)| range.priority).max();
+        let tied = covering.iter()
+            .filter(|(_, range)| Some(range.priority) == top)
+            .map(|(idx, _)| *idx)
+            .collect::<Vec<_>>();
+        let midnight = t.date().succ().and_hms(0, 0, 0);
+        let next = time_ranges.iter()
+            .filter_map(|time_range| time_range.next_boundary_after(t))
+            .fold(midnight, |a, b| a.min(b));
+        match ret.last_mut() {
+            Some(last) if last.0 == t.date().weekday() && last.3 == tied => last.2 = next.time(),
+            _ => ret.push((t.date().weekday(), t.time(), next.time(), tied)),
+        }
+        t = next;
+    }
+    ret
+}
+
+impl RobotWorkTime {
+    /// Warns about shifts longer than `min_shift` during which the robot never rests.
+    pub fn warnings(&self, min_shift: Duration) -> Vec<Warning> {
+        let shift = self.end - self.start;
+        if shift <= min_shift {
+            return vec![];
+        }
+        let break_begin = cycle_start(self.break_anchor, self.work_duration, self.rest_duration, self.start)
+            .and_then(|work_start| work_start.checked_add_signed(self.work_duration));
+        let rests = match break_begin {
+            // a break in progress at the start counts as well
+            Some(break_begin) => break_begin < self.end,
+            None => false,
+        };
+        if rests {
+            vec![]
+        } else {
+            vec![Warning::NoBreaks { shift, work: self.work_duration }]
+        }
+    }
+
+    /// The indices of the time ranges which never apply during the shift, e.g. as none of their weekdays falls
+    /// within it, so that configs can be trimmed. Ranges shadowed by others are not reported.
+    pub fn unreachable_ranges(&self) -> Vec<usize> {
+        self.time_range.iter().enumerate()
+            .filter(|(_, range)| range.pieces_between(self.start, self.end).is_empty())
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+}
+
+impl RobotWorkSchema {
+    /// The bands which never apply during the shift, see `RobotWorkTime::unreachable_ranges`.
+    pub fn unreachable_bands(&self) -> Vec<&'static str> {
+        let bands = self.robo_rate.bands();
+        let (robot, _) = RobotWorkTime::from_schema(self);
+        robot.unreachable_ranges().into_iter().map(|idx| bands[idx].0).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use chrono::{NaiveDateTime, NaiveTime};
+
+    use super::*;
+    use crate::presets::all_week;
+    use crate::TimeRange;
+
+    const SCHEMA: &str = r#"{
+  "shift": {
+      "start": "2038-01-01T20:15:00",
+      "end": "2038-01-02T04:15:00"
+  },
+  "roboRate": {
+    "standardDay": { "start": "07:00:00", "end": "23:00:00", "value": 20 },
+    "standardNight": { "start": "23:00:00", "end": "07:00:00", "value": 25 },
+    "extraDay": { "start": "07:00:00", "end": "23:00:00", "value": 30 },
+    "extraNight": { "start": "23:00:00", "end": "07:00:00", "value": 35 }
+  }
+}"#;
+
+    #[test]
+    fn empty_band_warning_test() {
+        let mut schema = serde_json::from_str::<RobotWorkSchema>(SCHEMA).unwrap();
+        assert_eq!(schema.warnings(), vec![]);
+
+        schema.robo_rate.extra_night.end = schema.robo_rate.extra_night.start;
+        assert_eq!(schema.warnings(), vec![Warning::EmptyBand { band: "extraNight" }]);
+        assert_eq!(schema.warnings()[0].to_string(), "extraNight starts and ends at the same time, so it never applies");
+    }
+
+    #[test]
+    fn unreachable_ranges_test() {
+        let at = |s: &str| NaiveDateTime::from_str(s).unwrap();
+        // Monday to Tuesday
+        let robot = RobotWorkTime::new(at("2021-09-06T07:00:00"), at("2021-09-07T19:00:00"), crate::standard_ranges());
+        assert_eq!(robot.unreachable_ranges(), vec![2, 3]);
+        // a shift ending at midnight does not reach into Saturday
+        let robot = RobotWorkTime::new(at("2021-09-10T23:00:00"), at("2021-09-11T00:00:00"), crate::standard_ranges());
+        assert_eq!(robot.unreachable_ranges(), vec![0, 2, 3]);
+
+        // Friday evening to Saturday morning
+        let schema = serde_json::from_str::<RobotWorkSchema>(SCHEMA).unwrap();
+        assert_eq!(schema.unreachable_bands(), vec!["extraDay"]);
+    }
+
+    #[test]
+    fn lint_test() {
+        let mut schema = serde_json::from_str::<RobotWorkSchema>(SCHEMA).unwrap();
+        assert_eq!(schema.lint(), vec![]);
+
+        schema.robo_rate.standard_day.end = NaiveTime::from_hms(22, 0, 0);
+        schema.robo_rate.extra_night.end = schema.robo_rate.extra_night.start;
+        let lints = schema.lint();
+        assert_eq!(lints.len(), 2);
+        assert_eq!(lints[0].severity, Severity::Error);
+        assert_eq!(lints[0].message, "no band covers Mon 22:00:00");
+        assert_eq!(lints[1].severity, Severity::Warning);
+        assert_eq!(lints[1].message, "extraNight starts and ends at the same time, so it never applies");
+    }
+
+    #[test]
+    fn overlap_lint_test() {
+        let mut schema = serde_json::from_str::<RobotWorkSchema>(SCHEMA).unwrap();
+        schema.robo_rate.standard_night.start = NaiveTime::from_hms(22, 0, 0);
+        assert_eq!(schema.lint(), vec![Lint {
+            severity: Severity::Warning,
+            message: "standardDay and standardNight both apply on Mon 22:00:00, where standardNight wins".to_string(),
+            suggestion: "give standardDay a higher priority, or move its start or end".to_string(),
+        }]);
+
+        schema.robo_rate.standard_night.priority = 1;
+        assert_eq!(schema.lint(), vec![]);
+    }
+
+    #[test]
+    fn coverage_test() {
+        let mut schema = serde_json::from_str::<RobotWorkSchema>(SCHEMA).unwrap();
+        assert_eq!(schema.coverage(), Coverage { gaps: vec![], overlaps: vec![] });
+
+        schema.robo_rate.standard_day.end = NaiveTime::from_hms(22, 0, 0);
+        schema.robo_rate.extra_night.start = NaiveTime::from_hms(22, 0, 0);
+        let coverage = schema.coverage();
+        assert_eq!(coverage.gaps, vec![Interval {
+            weekdays: crate::presets::weekdays().collect(),
+            start: NaiveTime::from_hms(22, 0, 0),
+            end: NaiveTime::from_hms(23, 0, 0),
+            bands: vec![],
+        }]);
+        assert_eq!(coverage.overlaps, vec![Interval {
+            weekdays: crate::presets::weekend().collect(),
+            start: NaiveTime::from_hms(22, 0, 0),
+            end: NaiveTime::from_hms(23, 0, 0),
+            bands: vec!["extraDay", "extraNight"],
+        }]);
+    }
+
+    #[test]
+    fn no_breaks_warning_test() {
+        let builder = RobotWorkTime::builder()
+            .shift(NaiveDateTime::from_str("2038-01-01T08:00:00").unwrap(), NaiveDateTime::from_str("2038-01-01T18:00:00").unwrap())
+            .add_range(TimeRange::new((NaiveTime::from_hms(7, 0, 0), NaiveTime::from_hms(23, 0, 0)), all_week()))
+            .add_range(TimeRange::new((NaiveTime::from_hms(23, 0, 0), NaiveTime::from_hms(7, 0, 0)), all_week()));
+        assert_eq!(builder.clone().build().unwrap().warnings(Duration::hours(8)), vec![]);
+
+        let robot = builder.break_policy(Duration::hours(24), Duration::hours(1)).build().unwrap();
+        let warning = Warning::NoBreaks { shift: Duration::hours(10), work: Duration::hours(24) };
+        assert_eq!(robot.warnings(Duration::hours(8)), vec![warning.clone()]);
+        assert_eq!(warning.to_string(), "no breaks inserted over a shift of 600 minutes, as the robot works 1440 minutes between breaks");
+        assert_eq!(robot.warnings(Duration::hours(12)), vec![]);
+
+        // the stint under way at the start ends 300ms in, on the grid of 1.1s cycles from the anchor
+        let start = robot.start;
+        let ms = Duration::milliseconds;
+        let mut robot = robot.with_break_anchor(start - ms(3500));
+        robot.end = start + ms(400);
+        robot.work_duration = ms(500);
+        robot.rest_duration = ms(600);
+        assert_eq!(robot.warnings(ms(100)), vec![]);
+        robot.end = start + ms(300);
+        assert_eq!(robot.warnings(ms(100)), vec![Warning::NoBreaks { shift: ms(300), work: ms(500) }]);
+    }
+}
@@ -0,0 +1,18 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+const SCHEMA: &str = include_str!("../sample_input.json");
+
+#[test]
+fn calc_test() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_robot-rate-calculator"))
+        .arg("calc")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(SCHEMA.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "{\"value\":13725}\n");
+}